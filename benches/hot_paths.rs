@@ -0,0 +1,59 @@
+// cargo bench
+// Times the hot paths with std::time, each benchmark on its own seeded RNG stream so runs
+// are comparable. Every result is fed to black_box so the work can't be optimized away.
+use cgmath::*;
+use raytracing::camera::{Camera, CoordinateSystem};
+use raytracing::hittable::Hittable;
+use raytracing::integrator::{PathIntegrator, RayBias, Scene};
+use raytracing::material::Lambertian;
+use raytracing::ray::Ray;
+use raytracing::sphere::Sphere;
+use raytracing::render::RenderSettings;
+use raytracing::streaming::render_streaming;
+use std::hint::black_box;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::time::Instant;
+
+const SEED: u64 = 0;
+
+// Runs f iterations times after a short warm up, and prints the mean time per iteration.
+fn bench<T, F: FnMut() -> T>(name: &str, iterations: u32, mut f: F) {
+    raytracing::seed_rng(SEED, 0);
+    for _ in 0..iterations / 10 {
+        black_box(f());
+    }
+
+    raytracing::seed_rng(SEED, 1);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    let per_iteration = start.elapsed() / iterations;
+    println!("{:<32} {:>12?} per iteration ({} iterations)", name, per_iteration, iterations);
+}
+
+fn main() {
+    let ray = Ray::new(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+
+    let sphere = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+    bench("Sphere::hit", 10_000_000, || sphere.hit(black_box(&ray), 0.001, f64::MAX).map(|hit| hit.t));
+
+    let mut rng = ChaCha8Rng::seed_from_u64(SEED);
+    let list = raytracing::scenes::packed_spheres(100, (Vector3::new(-5.0, -5.0, -5.0), Vector3::new(5.0, 5.0, 5.0)), CoordinateSystem::native(), &mut rng);
+    bench("HittableList::hit (100 spheres)", 100_000, || list.hit(black_box(&ray), 0.001, f64::MAX).map(|hit| hit.t));
+
+    bench("random_in_unit_sphere", 10_000_000, raytracing::random_in_unit_sphere);
+
+    let objects = raytracing::scenes::default_with_checker_ground(CoordinateSystem::native());
+    let background = raytracing::background::Background::Gradient { intensity: 1.0 };
+    let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+    let camera = Camera::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 90.0, 1.5, 0.0, 2.0);
+    let settings = RenderSettings::new(120, 80, 8, 20, SEED);
+    let integrator = PathIntegrator::new();
+    bench("render 120x80 @ 8spp", 10, || {
+        let mut pixels = 0;
+        render_streaming(&settings, 16, &camera, &scene, &integrator, |tile| pixels += tile.pixels.len());
+        pixels
+    });
+}
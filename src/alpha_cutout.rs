@@ -0,0 +1,93 @@
+use crate::hittable::*;
+use crate::ray::Ray;
+use crate::texture::Texture;
+
+// How far past a cut-out hit to start looking for the next one, relative to its t.
+// A fixed step vanishes into float rounding far from the ray origin (1e-7 is lost
+// added to 1e9), and the same hit would come back forever.
+const CUTOUT_EPSILON: f64 = 1e-7;
+
+// Cuts holes in any object with an opacity texture, for foliage and fences.
+// Alpha is the average of the texture's channels at the hit's (u, v). Wherever it's
+// below threshold the hit is ignored and the ray carries on to whatever is behind.
+pub struct AlphaCutout<H: Hittable, T: Texture> {
+    object: H,
+    alpha: T,
+    threshold: f64,
+}
+
+impl<H: Hittable, T: Texture> AlphaCutout<H, T> {
+    pub fn new(object: H, alpha: T, threshold: f64) -> Self {
+        AlphaCutout {
+            object,
+            alpha,
+            threshold,
+        }
+    }
+}
+
+impl<H: Hittable, T: Texture> Hittable for AlphaCutout<H, T> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut t_min = t_min;
+        loop {
+            let hit = self.object.hit(ray, t_min, t_max)?;
+            let texel = self.alpha.value(hit.u, hit.v, hit.point);
+            if (texel.x + texel.y + texel.z) / 3.0 >= self.threshold {
+                return Some(hit);
+            }
+            // Past an infinite or NaN t there's nothing to step on to.
+            if !hit.t.is_finite() {
+                return None
+            }
+            t_min = hit.t + CUTOUT_EPSILON * hit.t.abs().max(1.0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::SolidColor;
+    use cgmath::*;
+
+    // Opaque where x > 0, see-through elsewhere.
+    struct RightHalf;
+
+    impl Texture for RightHalf {
+        fn value(&self, _: f64, _: f64, p: Vector3<f64>) -> Vector3<f64> {
+            if p.x > 0.0 { Vector3::new(1.0, 1.0, 1.0) } else { Vector3::new(0.0, 0.0, 0.0) }
+        }
+    }
+
+    fn grey() -> Lambertian {
+        Lambertian::new(Vector3::new(0.5, 0.5, 0.5))
+    }
+
+    #[test]
+    fn rays_pass_through_transparent_parts_only() {
+        let cutout = AlphaCutout::new(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, grey()), RightHalf, 0.5);
+
+        let opaque = Ray::new(Vector3::new(0.5, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit = cutout.hit(&opaque, 0.001, f64::INFINITY).expect("the opaque half should be hit");
+        assert!(hit.front_face && hit.point.z > 0.0);
+
+        let transparent = Ray::new(Vector3::new(-0.5, 0.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(cutout.hit(&transparent, 0.001, f64::INFINITY).is_none());
+
+        // In through the cut-out left half, out through the opaque right half's inside wall.
+        let sideways = Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        let hit = cutout.hit(&sideways, 0.001, f64::INFINITY).expect("the far wall is opaque");
+        assert!(!hit.front_face && hit.point.x > 0.0);
+    }
+
+    #[test]
+    fn far_away_cutouts_still_terminate() {
+        let far = Sphere::new(Vector3::new(0.0, 0.0, -1e10), 1e8, grey());
+        let cutout = AlphaCutout::new(far, SolidColor::new(Vector3::new(0.0, 0.0, 0.0)), 0.5);
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(cutout.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,149 @@
+use crate::ray::Ray;
+use cgmath::*;
+
+// How far either side of the horizon (in unit direction y) a HemiLight blends sky into ground.
+const HORIZON_BLEND: f64 = 0.05;
+
+// What a ray sees when it doesn't hit anything.
+pub enum Background {
+    // The same color in every direction.
+    Solid(Vector3<f64>),
+    // The book's white-to-blue sky, scaled by intensity.
+    // Anything above 1.0 lets the sky act as a proper light source for the scene.
+    Gradient { intensity: f64 },
+    // Studio-style lighting: sky color above the horizon, ground color below.
+    HemiLight {
+        sky_color: Vector3<f64>,
+        ground_color: Vector3<f64>,
+    },
+    // Weighted sum of other backgrounds. Weights aren't normalized,
+    // so [(a, 0.5), (b, 0.5)] is the average of a and b.
+    Composite(Vec<(Background, f64)>),
+    // Another background brightened by exposure stops (+1 doubles it) and multiplied
+    // per channel by tint. Applies to lighting as well as what the camera sees.
+    Graded {
+        background: Box<Background>,
+        exposure: f64,
+        tint: Vector3<f64>,
+    },
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Vector3<f64> {
+        // No direction to look up, so contribute nothing rather than NaN.
+        if ray.is_degenerate() {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+
+        // Normalize vector so we have y between -1 and 1.
+        let unit_dir = ray.dir.normalize();
+
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { intensity } => {
+                // Add 1 to y so y's bounds are [0.0, 2.0]
+                // Multiply that by 0.5 so the bounds are [0.0, 1.0]
+                // Let t be the scale (from [0.0, 1.0]) of white or blue.
+                let t = 0.5 * (unit_dir.y + 1.0);
+
+                // When t is 1 (max height), the first segment
+                // of addition is (1.0 - 1.0)*white so no white is produced at top
+                // When t is 0 (min height), second segment becomes
+                // 0*blue (no blue produced at bottom of image)
+                // In other words, linear interpolation.
+                *intensity * ((1.0-t)*Vector3::new(1.0, 1.0, 1.0) + t*Vector3::new(0.5, 0.7, 1.0))
+            }
+            Background::HemiLight { sky_color, ground_color } => {
+                // Unlike the gradient this is flat on each side, only blending
+                // in a thin band around the horizon so there's no hard seam.
+                let t = smoothstep(-HORIZON_BLEND, HORIZON_BLEND, unit_dir.y);
+                (1.0-t)*ground_color + t*sky_color
+            }
+            Background::Composite(layers) => {
+                layers.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, (layer, weight)| sum + *weight * layer.color(ray))
+            }
+            Background::Graded { background, exposure, tint } => {
+                exposure.exp2() * background.color(ray).zip(*tint, |c, t| c * t)
+            }
+        }
+    }
+}
+
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ray_towards(direction: Vector3<f64>) -> Ray {
+        Ray::new(Vector3::new(0.0, 0.0, 0.0), direction)
+    }
+
+    // Up, down, along the horizon and a few in between.
+    fn directions() -> Vec<Vector3<f64>> {
+        vec![
+            Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0), Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.3, 0.5, -2.0), Vector3::new(-1.0, -0.02, 0.4), Vector3::new(0.0, 0.01, 1.0),
+        ]
+    }
+
+    #[test]
+    fn hemi_light_is_pure_sky_straight_up_and_pure_ground_straight_down() {
+        let (sky_color, ground_color) = (Vector3::new(0.6, 0.8, 1.0), Vector3::new(0.3, 0.2, 0.1));
+        let hemi = Background::HemiLight { sky_color, ground_color };
+        assert_eq!(hemi.color(&ray_towards(Vector3::new(0.0, 3.0, 0.0))), sky_color);
+        assert_eq!(hemi.color(&ray_towards(Vector3::new(0.0, -3.0, 0.0))), ground_color);
+        // Halfway on the horizon.
+        assert!((hemi.color(&ray_towards(Vector3::new(1.0, 0.0, 0.0))) - 0.5 * (sky_color + ground_color)).magnitude() < 1e-12);
+    }
+
+    #[test]
+    fn half_black_half_white_is_mid_grey_everywhere() {
+        let composite = Background::Composite(vec![
+            (Background::Solid(Vector3::new(0.0, 0.0, 0.0)), 0.5),
+            (Background::Solid(Vector3::new(1.0, 1.0, 1.0)), 0.5),
+        ]);
+        for direction in directions() {
+            assert_eq!(composite.color(&ray_towards(direction)), Vector3::new(0.5, 0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn doubling_sky_intensity_doubles_light_on_the_ground() {
+        use crate::hittable_list::HittableList;
+        use crate::integrator::{Integrator, PathIntegrator, RayBias, Scene};
+        use crate::material::Lambertian;
+        use crate::sphere::Sphere;
+
+        // Looking straight down at the top of a huge ball, whose bounces all go to the sky.
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, -100.0, 0.0), 100.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let down = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let radiance = |intensity: f64, i: u64| {
+            let background = Background::Gradient { intensity };
+            let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+            crate::seed_rng(3, i);
+            PathIntegrator::new().radiance(&down, &scene, 50)
+        };
+        for i in 0..100 {
+            assert!(radiance(1.0, i).x > 0.0);
+            assert_eq!(radiance(2.0, i), 2.0 * radiance(1.0, i));
+        }
+    }
+
+    #[test]
+    fn one_stop_up_doubles_and_tint_scales_each_channel() {
+        let sky = || Box::new(Background::Gradient { intensity: 1.0 });
+        let brighter = Background::Graded { background: sky(), exposure: 1.0, tint: Vector3::new(1.0, 1.0, 1.0) };
+        let tinted = Background::Graded { background: sky(), exposure: 0.0, tint: Vector3::new(1.0, 0.5, 0.25) };
+        for direction in directions() {
+            let ray = ray_towards(direction);
+            let plain = sky().color(&ray);
+            assert_eq!(brighter.color(&ray), 2.0 * plain);
+            assert_eq!(tinted.color(&ray), Vector3::new(plain.x, 0.5 * plain.y, 0.25 * plain.z));
+        }
+    }
+}
@@ -3,6 +3,82 @@
 use crate::ray::*;
 use cgmath::*;
 
+// Which world axis a scene was authored with pointing "up".
+// The renderer itself is Y-up (the sky gradient blends along y), so Z-up scenes
+// from CAD/Blender get converted on the way in instead of being rotated by hand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpAxis {
+    Y,
+    Z,
+}
+
+impl UpAxis {
+    // The up vector in this convention's own coordinates.
+    pub fn up(&self) -> Vector3<f64> {
+        match self {
+            UpAxis::Y => Vector3::new(0.0, 1.0, 0.0),
+            UpAxis::Z => Vector3::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    // Rotate a point or direction from this convention into the renderer's Y-up space.
+    // Z-up's forward (+y) becomes -z so the result stays right-handed.
+    pub fn to_y_up(&self, v: Vector3<f64>) -> Vector3<f64> {
+        match self {
+            UpAxis::Y => v,
+            UpAxis::Z => Vector3::new(v.x, v.z, -v.y),
+        }
+    }
+}
+
+// Whether a scene was authored in a left- or right-handed coordinate system.
+// The renderer is right-handed, importing a left-handed scene without flipping mirrors it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Handedness {
+    Right,
+    Left,
+}
+
+// The coordinate conventions a scene was authored in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoordinateSystem {
+    pub up: UpAxis,
+    pub handedness: Handedness,
+}
+
+impl CoordinateSystem {
+    // The renderer's own conventions: Y-up, right-handed.
+    pub fn native() -> Self {
+        CoordinateSystem {
+            up: UpAxis::Y,
+            handedness: Handedness::Right,
+        }
+    }
+
+    // Convert a point or direction authored in this system into the renderer's space.
+    // Left-handed scenes get their forward axis (the one that's neither x nor up) mirrored first.
+    pub fn to_world(&self, v: Vector3<f64>) -> Vector3<f64> {
+        let right_handed = match (self.handedness, self.up) {
+            (Handedness::Right, _) => v,
+            (Handedness::Left, UpAxis::Y) => Vector3::new(v.x, v.y, -v.z),
+            (Handedness::Left, UpAxis::Z) => Vector3::new(v.x, -v.y, v.z),
+        };
+        self.up.to_y_up(right_handed)
+    }
+
+    // The up vector in renderer space.
+    pub fn up(&self) -> Vector3<f64> {
+        self.to_world(self.up.up())
+    }
+}
+
+// Rows of an image img_width pixels across that displays at aspect_ratio (width over height)
+// once each pixel is shown pixel_aspect_ratio times as wide as it is tall. A 2:1 anamorphic
+// squeeze stores twice the rows square pixels would need.
+pub fn image_height(img_width: i32, aspect_ratio: f64, pixel_aspect_ratio: f64) -> i32 {
+    ((img_width as f64 * pixel_aspect_ratio / aspect_ratio) as i32).max(1)
+}
+
 pub struct Camera {
     origin: Vector3<f64>,
     lower_left_corner: Vector3<f64>,
@@ -12,6 +88,11 @@ pub struct Camera {
     u: Vector3<f64>,
     v: Vector3<f64>,
     w: Vector3<f64>,
+    near: f64,
+    far: f64,
+    projection_blend: f64,
+    focus_dist: f64,
+    skip_object: usize,
 }
 
 impl Camera {
@@ -30,9 +111,24 @@ impl Camera {
         let viewport_width = aspect_ratio * viewport_height;
 
         // w is our camera's Z axis.
-        let w = (look_from - look_at).normalize();
+        // Looking at our own position has no direction, so fall back to looking down -Z.
+        let w = if (look_from - look_at).magnitude2() > 1e-16 {
+            (look_from - look_at).normalize()
+        } else {
+            eprintln!("Camera look_from and look_at are the same point, looking down -Z instead");
+            Vector3::new(0.0, 0.0, 1.0)
+        };
         // u is our camera's X axis.
-        let u = v_up.cross(w).normalize();
+        // An up along the view direction doesn't say which way is up, so pick one that
+        // isn't: Y, or -Z when looking straight up or down.
+        let side = v_up.cross(w);
+        let u = if side.magnitude2() > 1e-16 {
+            side.normalize()
+        } else {
+            eprintln!("Camera v_up {:?} is along the view direction, using another up", v_up);
+            let fallback_up = if w.y.abs() < 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(0.0, 0.0, -1.0) };
+            fallback_up.cross(w).normalize()
+        };
         // v is our camera's Y axis.
         let v = w.cross(u);
 
@@ -48,16 +144,166 @@ impl Camera {
             lower_left_corner: look_from - (focus_dist * viewport_width * u)/2.0 - (focus_dist * viewport_height * v)/2.0 - focus_dist*w,
             
             lens_radius: aperture/2.0,
-            u: u,
-            v: v,
-            w: w,
+            u,
+            v,
+            w,
+            near: 0.0,
+            far: f64::INFINITY,
+            projection_blend: 0.0,
+            focus_dist,
+            skip_object: crate::hittable::BACKGROUND_ID,
         }
     }
 
+    // Camera rays pass straight through the HittableList object with this id,
+    // for looking out from inside something like a glass dome.
+    pub fn with_skipped_object(mut self, id: usize) -> Camera {
+        self.skip_object = id;
+        self
+    }
+
+    // Blend from perspective (0.0) to orthographic (1.0) projection. Rays still converge on
+    // the same focus plane, so anything in between flattens the scene's perspective while
+    // keeping the lens blur, for the miniature look of a tilt-shift.
+    pub fn with_projection_blend(mut self, blend: f64) -> Camera {
+        self.projection_blend = blend.clamp(0.0, 1.0);
+        self
+    }
+
+    // Clip primary rays to geometry between near and far, measured along the view axis
+    // so the clip surfaces are planes rather than spheres around the camera.
+    pub fn with_clipping(mut self, near: f64, far: f64) -> Camera {
+        self.near = near.max(0.0);
+        self.far = far;
+        self
+    }
+
     pub fn get_ray(&self, s: f64, t: f64) -> Ray {
         let rd = self.lens_radius * crate::random_in_unit_disk();
         let offset = self.u * rd.x + self.v * rd.y;
 
-        Ray::new(self.origin + offset, self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin - offset)
+        // Where this ray crosses the focus plane. A pinhole ray comes from the eye, an
+        // orthographic one from straight behind that point, level with the eye.
+        let target = self.lower_left_corner + s*self.horizontal + t*self.vertical;
+        let ortho_origin = target + self.focus_dist * self.w;
+        let origin = self.origin + self.projection_blend * (ortho_origin - self.origin) + offset;
+        let dir = target - origin;
+
+        // Depth along the view axis per unit of t.
+        let depth_rate = dir.dot(-self.w);
+        let mut ray = Ray::new(origin, dir);
+        ray.skip_object = self.skip_object;
+        if self.near <= 0.0 && self.far == f64::INFINITY || depth_rate <= 0.0 {
+            return ray;
+        }
+
+        // Start the ray on the near plane so nothing in front of it can be hit.
+        let t_near = self.near / depth_rate;
+        ray.origin += t_near * dir;
+        ray.t_max = self.far / depth_rate - t_near;
+        ray
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_finite(v: Vector3<f64>) -> bool {
+        v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+    }
+
+    // Rays through a grid across the whole view.
+    fn rays(camera: &Camera) -> Vec<Ray> {
+        (0..25).map(|i| camera.get_ray((i % 5) as f64 / 4.0, (i / 5) as f64 / 4.0)).collect()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn degenerate_views_still_give_finite_rays() {
+        let p = Vector3::new(1.0, 2.0, 3.0);
+        let cameras = [
+            // Looking at itself, with an up that's fine for the fallback -Z view and one that isn't.
+            Camera::new(p, p, Vector3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.1, 1.0),
+            Camera::new(p, p, Vector3::new(0.0, 0.0, 1.0), 40.0, 1.5, 0.1, 1.0),
+            // Straight down, up along the view, and no up at all.
+            Camera::new(p, p - Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.1, 1.0),
+            Camera::new(p, p + Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0), 40.0, 1.5, 0.1, 1.0),
+        ];
+        for camera in cameras.iter() {
+            for ray in rays(camera) {
+                assert!(all_finite(ray.origin) && all_finite(ray.dir), "{:?} {:?}", ray.origin, ray.dir);
+                assert!(!ray.is_degenerate());
+            }
+        }
+    }
+
+    #[test]
+    fn clipping_keeps_only_what_is_between_near_and_far() {
+        use crate::hittable::Hittable;
+        use crate::material::Lambertian;
+        use crate::sphere::Sphere;
+
+        let camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.0, 1.0)
+            .with_clipping(2.0, 10.0);
+        let at = |z: f64| Sphere::new(Vector3::new(0.0, 0.0, z), 0.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+        let seen = |camera: &Camera, z: f64| {
+            rays(camera).iter().any(|ray| at(z).hit(ray, 0.001, ray.t_max).is_some())
+        };
+
+        assert!(!seen(&camera, -1.0));
+        assert!(seen(&camera, -5.0));
+        assert!(!seen(&camera, -12.0));
+        // Without clipping all three are in view.
+        let unclipped = Camera { near: 0.0, far: f64::INFINITY, ..camera };
+        for z in [-1.0, -5.0, -12.0].iter() {
+            assert!(seen(&unclipped, *z));
+        }
+    }
+
+    #[test]
+    fn projection_blend_moves_origins_linearly_to_behind_the_focus_plane() {
+        let look_from = Vector3::new(1.0, 2.0, 3.0);
+        let camera = |blend| Camera::new(look_from, Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.0, 3.0)
+            .with_projection_blend(blend);
+        let w = (look_from - Vector3::new(0.0, 0.0, -1.0)).normalize();
+
+        for (i, pinhole) in rays(&camera(0.0)).iter().enumerate() {
+            assert_eq!(pinhole.origin, look_from);
+            // Every blend aims at the same point on the focus plane.
+            let target = pinhole.origin + pinhole.dir;
+            let ortho_origin = target + 3.0 * w;
+            for blend in [0.25, 0.5, 1.0].iter() {
+                let ray = &rays(&camera(*blend))[i];
+                assert!((ray.origin - (look_from + *blend * (ortho_origin - look_from))).magnitude() < 1e-12);
+                assert!((ray.origin + ray.dir - target).magnitude() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn skipping_the_enclosing_object_sees_out_of_it() {
+        use crate::hittable::Hittable;
+        use crate::hittable_list::HittableList;
+        use crate::material::Lambertian;
+        use crate::sphere::Sphere;
+
+        // A dome around the camera and a ball outside it, straight ahead.
+        let mut objects = HittableList::new();
+        let dome = objects.push(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 5.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let ball = objects.push(Sphere::new(Vector3::new(0.0, 0.0, -10.0), 3.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let camera = || Camera::new(origin, Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 40.0, 1.5, 0.0, 1.0);
+        assert_eq!(objects.enclosing(origin), Some(dome));
+
+        // (object id, front face) of what each ray hits.
+        let hits = |camera: &Camera| -> Vec<Option<(usize, bool)>> {
+            rays(camera).iter().map(|ray| objects.hit(ray, 0.001, ray.t_max).map(|hit| (hit.object_id, hit.front_face))).collect()
+        };
+        // Without skipping it, only the dome's inside wall.
+        assert!(hits(&camera()).iter().all(|hit| *hit == Some((dome, false))));
+        // Skipping it, the ball is in the middle of the view and nothing sees the dome.
+        let beyond = hits(&camera().with_skipped_object(objects.enclosing(origin).unwrap()));
+        assert_eq!(beyond[12], Some((ball, true)));
+        assert!(beyond.iter().all(|hit| hit.map(|(id, _)| id) != Some(dome)));
+    }
+}
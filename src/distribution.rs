@@ -0,0 +1,107 @@
+use cgmath::*;
+use rand::Rng;
+
+// How a diffuse material picks its bounce direction around the surface normal.
+// The book walks through a few of these approximations, this lets a material pick one.
+pub trait ScatterDistribution: Send + Sync {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R, normal: Vector3<f64>) -> Vector3<f64>;
+
+    // Probability density, over solid angle, of sample returning a direction along direction.
+    fn pdf(&self, normal: Vector3<f64>, direction: Vector3<f64>) -> f64;
+}
+
+// Cosine of the angle between the normal and direction, 0 below the surface.
+fn cosine(normal: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+    normal.normalize().dot(direction.normalize()).max(0.0)
+}
+
+// normal + a point inside the unit sphere. The book's first diffuse approximation,
+// biased towards the normal more than a true Lambertian.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InUnitSphere;
+
+// normal + a point on the unit sphere. Gives a cosine distribution, i.e. true Lambertian.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnitVector;
+
+// Uniformly distributed over the hemisphere around the normal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hemisphere;
+
+impl ScatterDistribution for InUnitSphere {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R, normal: Vector3<f64>) -> Vector3<f64> {
+        normal + crate::sample_in_unit_sphere(rng)
+    }
+
+    // The ball of points along a direction at angle theta reaches out to 2cos(theta),
+    // integrating t^2 over that and dividing by the ball's volume gives 2cos^3(theta)/pi.
+    fn pdf(&self, normal: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        2.0 * cosine(normal, direction).powi(3) / crate::PI
+    }
+}
+
+impl ScatterDistribution for UnitVector {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R, normal: Vector3<f64>) -> Vector3<f64> {
+        normal + crate::sample_in_unit_sphere(rng).normalize()
+    }
+
+    fn pdf(&self, normal: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        cosine(normal, direction) / crate::PI
+    }
+}
+
+impl ScatterDistribution for Hemisphere {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R, normal: Vector3<f64>) -> Vector3<f64> {
+        let in_unit_sphere = crate::sample_in_unit_sphere(rng);
+        // Flip anything that landed below the surface back up into the hemisphere.
+        if in_unit_sphere.dot(normal) > 0.0 { in_unit_sphere } else { -in_unit_sphere }
+    }
+
+    fn pdf(&self, normal: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        if cosine(normal, direction) > 0.0 { 1.0 / (2.0 * crate::PI) } else { 0.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn normals() -> Vec<Vector3<f64>> {
+        vec![Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(1.0, -2.0, 0.5).normalize()]
+    }
+
+    fn stays_above<D: ScatterDistribution>(distribution: D) {
+        let mut rng = ChaCha8Rng::seed_from_u64(8);
+        for normal in normals() {
+            for _ in 0..2000 {
+                assert!(distribution.sample(&mut rng, normal).dot(normal) > 0.0);
+            }
+        }
+    }
+
+    // Monte Carlo integral of pdf over the whole sphere of directions.
+    fn pdf_integral<D: ScatterDistribution>(distribution: D, normal: Vector3<f64>) -> f64 {
+        let mut rng = ChaCha8Rng::seed_from_u64(9);
+        let samples = 40_000;
+        let sum: f64 = (0..samples).map(|_| distribution.pdf(normal, crate::sample_in_unit_sphere(&mut rng).normalize())).sum();
+        4.0 * crate::PI * sum / samples as f64
+    }
+
+    #[test]
+    fn samples_stay_in_the_normals_hemisphere() {
+        stays_above(InUnitSphere);
+        stays_above(UnitVector);
+        stays_above(Hemisphere);
+    }
+
+    #[test]
+    fn pdfs_integrate_to_one() {
+        for normal in normals() {
+            for integral in [pdf_integral(InUnitSphere, normal), pdf_integral(UnitVector, normal), pdf_integral(Hemisphere, normal)].iter() {
+                assert!((integral - 1.0).abs() < 0.03, "integral {} around {:?}", integral, normal);
+            }
+        }
+    }
+}
@@ -1,17 +1,48 @@
 use crate::material::Material;
 use cgmath::*;
 use crate::ray::Ray;
+use rand::RngCore;
+
+// Id reported for rays that don't hit anything. Objects in a HittableList start at 1.
+pub const BACKGROUND_ID: usize = 0;
 
 pub trait Hittable: Send + Sync {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
+
+    // Whether anything at all is hit in [t_min, t_max]. Shadow/occlusion rays only
+    // need this, so aggregates can stop at the first hit instead of finding the closest.
+    fn hit_any(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit(ray, t_min, t_max).is_some()
+    }
+
+    // Probability density, over solid angle, of random() picking direction from origin.
+    // Objects that don't support being sampled towards (the default) have zero density.
+    fn pdf_value(&self, _origin: Vector3<f64>, _direction: Vector3<f64>) -> f64 {
+        0.0
+    }
+
+    // A random direction from origin towards this object, distributed by pdf_value.
+    fn random(&self, _origin: Vector3<f64>, _rng: &mut dyn RngCore) -> Vector3<f64> {
+        Vector3::new(0.0, 0.0, 1.0)
+    }
+
+    // Whether point is inside this object. Only closed solids have an inside.
+    fn contains(&self, _point: Vector3<f64>) -> bool {
+        false
+    }
 }
 
 pub struct HitRecord<'a> {
     pub point: Vector3::<f64>,
     pub normal: Vector3::<f64>,
     pub t: f64,
+    // Surface coordinates of the hit, each in [0, 1].
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
-    pub material: &'a dyn Material
+    pub material: &'a dyn Material,
+    // Id of the object in its HittableList, 0 (BACKGROUND_ID) if it isn't in one.
+    pub object_id: usize,
 }
 
 impl HitRecord<'_> {
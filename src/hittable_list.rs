@@ -1,33 +1,226 @@
 use crate::ray::Ray;
+use cgmath::Vector3;
+use rand::{Rng, RngCore};
 use crate::hittable::*;
 
 pub struct HittableList {
-    pub objects: Vec<Box<dyn Hittable>>
+    objects: Vec<Box<dyn Hittable>>,
+    // ids[i] is the id of objects[i]. Ids count up from 1 and are never reused,
+    // so one stays valid (or stays gone) whatever else is removed.
+    ids: Vec<usize>,
+    next_id: usize,
 }
 
 impl HittableList {
     pub fn new() -> Self {
         Self {
-            objects: Vec::<Box<dyn Hittable>>::new()
+            objects: Vec::<Box<dyn Hittable>>::new(),
+            ids: Vec::new(),
+            next_id: BACKGROUND_ID + 1,
         }
     }
     //                                                    ???
-    pub fn push(&mut self, object: impl Hittable + 'static) {
+    // Returns the id hits on this object will report.
+    pub fn push(&mut self, object: impl Hittable + 'static) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
         self.objects.push(Box::new(object));
+        self.ids.push(id);
+        id
+    }
+
+    // Takes the object with this id out of the list. False if there's no such object.
+    pub fn remove(&mut self, id: usize) -> bool {
+        match self.index_of(id) {
+            Some(index) => {
+                self.objects.remove(index);
+                self.ids.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Swaps the object with this id for another, e.g. to change its material, keeping the id.
+    // False if there's no such object.
+    pub fn replace(&mut self, id: usize, object: impl Hittable + 'static) -> bool {
+        match self.index_of(id) {
+            Some(index) => {
+                self.objects[index] = Box::new(object);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // Largest id handed out so far, BACKGROUND_ID if nothing has been pushed.
+    pub fn max_id(&self) -> usize {
+        self.next_id - 1
+    }
+
+    // Id of the first object point is inside, if any.
+    pub fn enclosing(&self, point: Vector3<f64>) -> Option<usize> {
+        self.objects.iter().zip(self.ids.iter()).find(|(object, _)| object.contains(point)).map(|(_, id)| *id)
+    }
+
+    // Ids are handed out in increasing order, so they stay sorted.
+    fn index_of(&self, id: usize) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
+    }
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         let mut hit_anything: Option<HitRecord> = None;
         let mut closest_so_far: f64 = t_max;
 
-        for object in self.objects.iter() {
-            if let Some(hit) = object.hit(ray, t_min, closest_so_far) {
+        for (index, object) in self.objects.iter().enumerate() {
+            if self.ids[index] == ray.skip_object {
+                continue;
+            }
+            if let Some(mut hit) = object.hit(ray, t_min, closest_so_far) {
                 closest_so_far = hit.t;
+                hit.object_id = self.ids[index];
                 hit_anything = Some(hit);
             }
         }
         hit_anything
     }
-}
\ No newline at end of file
+
+    fn hit_any(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.objects.iter().zip(self.ids.iter())
+            .any(|(object, id)| *id != ray.skip_object && object.hit_any(ray, t_min, t_max))
+    }
+
+    // Sampling picks one object uniformly, so the density is the average of theirs.
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        if self.objects.is_empty() { return 0.0 }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
+    }
+
+    fn random(&self, origin: Vector3<f64>, rng: &mut dyn RngCore) -> Vector3<f64> {
+        if self.objects.is_empty() { return Vector3::new(0.0, 0.0, 1.0) }
+        let index = rng.gen_range(0, self.objects.len());
+        self.objects[index].random(origin, rng)
+    }
+
+    fn contains(&self, point: Vector3<f64>) -> bool {
+        self.objects.iter().any(|object| object.contains(point))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn sphere_at(z: f64) -> Sphere<Lambertian> {
+        Sphere::new(Vector3::new(0.0, 0.0, z), 0.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)))
+    }
+
+    // Along -z from the origin, so nearer spheres are hit first.
+    fn first_hit(list: &HittableList) -> Option<usize> {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        list.hit(&ray, 0.001, f64::INFINITY).map(|hit| hit.object_id)
+    }
+
+    #[test]
+    fn removed_objects_are_no_longer_hit() {
+        let mut list = HittableList::new();
+        let near = list.push(sphere_at(-2.0));
+        let far = list.push(sphere_at(-4.0));
+        assert_eq!(first_hit(&list), Some(near));
+
+        assert!(list.remove(near));
+        assert_eq!(first_hit(&list), Some(far));
+        assert!(!list.remove(near));
+
+        assert!(list.remove(far));
+        assert_eq!(first_hit(&list), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn removed_ids_are_never_reused() {
+        let mut list = HittableList::new();
+        let first = list.push(sphere_at(-2.0));
+        let second = list.push(sphere_at(-4.0));
+        list.remove(second);
+        let third = list.push(sphere_at(-6.0));
+        assert!(third != first && third != second && third != BACKGROUND_ID);
+
+        // Replacing keeps the id, a removed id can't be brought back.
+        assert!(list.replace(first, sphere_at(-3.0)));
+        assert!(!list.replace(second, sphere_at(-3.0)));
+        assert_eq!(first_hit(&list), Some(first));
+        assert_eq!(list.len(), 2);
+    }
+
+    // Never hit, but counts how often it's asked.
+    struct Counting(&'static AtomicUsize);
+
+    impl Hittable for Counting {
+        fn hit(&self, _: &Ray, _: f64, _: f64) -> Option<HitRecord<'_>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            None
+        }
+    }
+
+    #[test]
+    fn hit_any_agrees_with_hit() {
+        let mut list = HittableList::new();
+        for (i, z) in [-2.0, -3.5, -6.0].iter().enumerate() {
+            list.push(Sphere::new(Vector3::new(0.4 * i as f64 - 0.4, 0.0, *z), 0.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        }
+
+        let mut hits = 0;
+        for i in 0..400 {
+            let direction = Vector3::new((i % 20) as f64 * 0.02 - 0.2, (i / 20) as f64 * 0.02 - 0.2, -1.0);
+            let mut ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), direction);
+            for t_max in [f64::INFINITY, 3.0, 1.0].iter() {
+                assert_eq!(list.hit_any(&ray, 0.001, *t_max), list.hit(&ray, 0.001, *t_max).is_some());
+            }
+            if let Some(hit) = list.hit(&ray, 0.001, f64::INFINITY) {
+                hits += 1;
+                // Skipping the object it hits leaves the two still agreeing on what's behind it.
+                ray.skip_object = hit.object_id;
+                assert_eq!(list.hit_any(&ray, 0.001, f64::INFINITY), list.hit(&ray, 0.001, f64::INFINITY).is_some());
+                assert!(list.hit(&ray, 0.001, f64::INFINITY).is_none_or(|behind| behind.object_id != hit.object_id));
+            }
+        }
+        assert!(hits > 50 && hits < 400);
+    }
+
+    #[test]
+    fn hit_any_stops_at_the_first_occluder() {
+        let mut list = HittableList::new();
+        let occluder = list.push(sphere_at(-2.0));
+        static ASKED: AtomicUsize = AtomicUsize::new(0);
+        list.push(Counting(&ASKED));
+
+        let mut ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(list.hit_any(&ray, 0.001, f64::INFINITY));
+        assert_eq!(ASKED.load(Ordering::SeqCst), 0);
+
+        // With the occluder skipped there's nothing left to hit.
+        ray.skip_object = occluder;
+        assert!(!list.hit_any(&ray, 0.001, f64::INFINITY));
+        assert_eq!(ASKED.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,630 @@
+use crate::background::Background;
+use crate::hittable::Hittable;
+use crate::ray::Ray;
+use cgmath::*;
+
+// Closest a spawned ray may hit, keeps rays from re-hitting the surface they left ("acne").
+pub const RAY_BIAS: f64 = 0.001;
+
+// Same for shadow rays, which only ask whether anything is in the way. Too small and a surface
+// shadows itself in speckles, too big and shadows pull away from the objects casting them.
+pub const SHADOW_BIAS: f64 = 0.001;
+
+// Magenta stands out against just about anything a scene would render.
+pub const BACKFACE_COLOR: Vector3<f64> = Vector3 { x: 1.0, y: 0.0, z: 1.0 };
+
+// Everything an integrator can see of the scene.
+pub struct Scene<'a> {
+    pub objects: &'a dyn Hittable,
+    pub background: &'a Background,
+    // What scattered rays that miss see, when it should differ from the backdrop the camera
+    // sees, e.g. a photo behind the objects while the sky lights them. None uses background.
+    pub lighting: Option<&'a Background>,
+    pub bias: RayBias,
+}
+
+impl<'a> Scene<'a> {
+    // Color of a ray that missed everything. primary is whether it came straight from the camera.
+    pub fn miss(&self, ray: &Ray, primary: bool) -> Vector3<f64> {
+        match self.lighting {
+            Some(lighting) if !primary => lighting.color(ray),
+            _ => self.background.color(ray),
+        }
+    }
+}
+
+// Closest hits counted along camera and scattered rays (scatter) and along visibility
+// rays (shadow). They trade off acne against leaks differently, so they're tuned separately.
+#[derive(Clone, Copy, Debug)]
+pub struct RayBias {
+    pub scatter: f64,
+    pub shadow: f64,
+}
+
+impl Default for RayBias {
+    fn default() -> Self {
+        RayBias {
+            scatter: RAY_BIAS,
+            shadow: SHADOW_BIAS,
+        }
+    }
+}
+
+// Turns a camera ray into the color it contributes. depth is how many more
+// bounces the integrator is allowed before giving up.
+pub trait Integrator: Send + Sync {
+    fn radiance(&self, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64>;
+}
+
+// How many bounces of each kind a path may take, on top of the overall depth limit.
+// Glass needs a lot of transmission bounces to look right, diffuse interreflection rarely
+// needs many, so this lets a render spend its bounces where they matter.
+#[derive(Clone, Copy, Debug)]
+pub struct BounceBudget {
+    pub diffuse: i32,
+    pub specular: i32,
+    pub transmission: i32,
+}
+
+impl BounceBudget {
+    // Only the overall depth limit applies.
+    pub fn unlimited() -> Self {
+        BounceBudget {
+            diffuse: i32::MAX,
+            specular: i32::MAX,
+            transmission: i32::MAX,
+        }
+    }
+}
+
+// The book's recursive path tracer.
+pub struct PathIntegrator {
+    pub budget: BounceBudget,
+    // Most any one bounce may hand back, per channel. Deliberately not physical, it's for
+    // taming harsh specular hotspots by eye. f64::INFINITY leaves paths alone.
+    pub bounce_ceiling: f64,
+    // Every bounce past soft_cutoff scales the rest of the path by soft_falloff, fading long
+    // paths out gradually rather than cutting them at the depth limit. Biased, but quieter
+    // in scenes with lots of interreflection. i32::MAX turns it off.
+    pub soft_cutoff: i32,
+    pub soft_falloff: f64,
+}
+
+impl PathIntegrator {
+    pub fn new() -> Self {
+        PathIntegrator::with_budget(BounceBudget::unlimited())
+    }
+
+    pub fn with_budget(budget: BounceBudget) -> Self {
+        PathIntegrator {
+            budget,
+            bounce_ceiling: f64::INFINITY,
+            soft_cutoff: i32::MAX,
+            soft_falloff: 1.0,
+        }
+    }
+
+    pub fn with_soft_cutoff(mut self, bounces: i32, falloff: f64) -> Self {
+        self.soft_cutoff = bounces;
+        self.soft_falloff = falloff;
+        self
+    }
+
+    pub fn with_bounce_ceiling(mut self, ceiling: f64) -> Self {
+        self.bounce_ceiling = ceiling;
+        self
+    }
+
+    // bounce is how many times the path has scattered before reaching ray.
+    fn trace(&self, ray: &Ray, scene: &Scene, depth: i32, budget: BounceBudget, bounce: i32) -> Vector3<f64> {
+        // Don't let the stack overflow
+        if depth <= 0 {
+            return Vector3::<f64>::new(0.0, 0.0, 0.0);
+        }
+
+        if let Some(hit) = scene.objects.hit(ray, scene.bias.scatter, ray.t_max) {
+            if let Some((r, atten)) = hit.material.scatter(ray, &hit) {
+                // Leaving through the far side of the surface is transmission.
+                let mut budget = budget;
+                let remaining = if r.dir.dot(hit.normal) < 0.0 {
+                    &mut budget.transmission
+                } else if hit.material.is_specular() {
+                    &mut budget.specular
+                } else {
+                    &mut budget.diffuse
+                };
+                if *remaining <= 0 {
+                    return Vector3::<f64>::new(0.0, 0.0, 0.0);
+                }
+                *remaining -= 1;
+
+                let mut bounced = atten.zip(self.trace(&r, scene, depth-1, budget, bounce + 1), |l, r| l * r);
+                if bounce >= self.soft_cutoff {
+                    bounced *= self.soft_falloff;
+                }
+                if self.bounce_ceiling.is_finite() {
+                    return bounced.map(|c| c.min(self.bounce_ceiling));
+                }
+                return bounced;
+            }
+            return Vector3::<f64>::new(0.0, 0.0, 0.0);
+        }
+        scene.miss(ray, bounce == 0)
+    }
+}
+
+impl Default for PathIntegrator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64> {
+        self.trace(ray, scene, depth, self.budget, 0)
+    }
+}
+
+// Ambient occlusion: white where a ray from the hit, spread as sampling says, escapes
+// within distance, black where it's blocked. Misses are fully unoccluded.
+pub struct AmbientOcclusionIntegrator {
+    pub distance: f64,
+    pub sampling: OcclusionSampling,
+}
+
+// Which directions ambient occlusion looks for blockers in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OcclusionSampling {
+    // Cosine weighted over the hemisphere above the surface.
+    Hemisphere,
+    // Uniform over the whole sphere, so blockers behind thin or translucent surfaces count too.
+    Sphere,
+}
+
+impl Integrator for AmbientOcclusionIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _: i32) -> Vector3<f64> {
+        let white = Vector3::new(1.0, 1.0, 1.0);
+        match scene.objects.hit(ray, scene.bias.scatter, ray.t_max) {
+            Some(hit) => {
+                let direction = match self.sampling {
+                    OcclusionSampling::Hemisphere => (hit.normal + crate::random_unit_vector()).normalize(),
+                    OcclusionSampling::Sphere => crate::random_unit_vector(),
+                };
+                if scene.objects.hit_any(&Ray::new(hit.point, direction), scene.bias.shadow, self.distance) {
+                    Vector3::new(0.0, 0.0, 0.0)
+                } else {
+                    white
+                }
+            }
+            None => white,
+        }
+    }
+}
+
+// Shading normal of the first hit mapped from [-1, 1] to [0, 1] per axis. Misses are black.
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _: i32) -> Vector3<f64> {
+        match scene.objects.hit(ray, scene.bias.scatter, ray.t_max) {
+            Some(hit) => 0.5 * (hit.normal + Vector3::new(1.0, 1.0, 1.0)),
+            None => Vector3::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// Only light that reaches a surface straight from the background: one scatter,
+// and the scattered ray has to escape. No interreflection.
+// light_samples scattered rays are averaged per hit, so soft shadows can be smoothed
+// without paying for more camera rays.
+pub struct DirectIntegrator {
+    pub light_samples: u32,
+}
+
+impl Integrator for DirectIntegrator {
+    fn radiance(&self, ray: &Ray, scene: &Scene, _: i32) -> Vector3<f64> {
+        let hit = match scene.objects.hit(ray, scene.bias.scatter, ray.t_max) {
+            Some(hit) => hit,
+            None => return scene.miss(ray, true),
+        };
+
+        let light_samples = self.light_samples.max(1);
+        let sum: Vector3<f64> = (0..light_samples).map(|_| {
+            match hit.material.scatter(ray, &hit) {
+                Some((scattered, atten)) if !scene.objects.hit_any(&scattered, scene.bias.shadow, f64::MAX) => {
+                    atten.zip(scene.miss(&scattered, false), |l, r| l * r)
+                }
+                _ => Vector3::new(0.0, 0.0, 0.0),
+            }
+        }).sum();
+        sum / light_samples as f64
+    }
+}
+
+// Renders with inner, but caps camera rays' t_max so they ignore every object farther than
+// distance away and show the background instead. A distance of 0.0 hides everything, for
+// setting up the sky or environment on its own before there's anything in the scene to
+// block it. Scattered rays still see the whole scene.
+pub struct BackgroundIntegrator<I: Integrator> {
+    pub inner: I,
+    pub distance: f64,
+}
+
+impl<I: Integrator> Integrator for BackgroundIntegrator<I> {
+    fn radiance(&self, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64> {
+        if self.distance.is_nan() || self.distance <= 0.0 || ray.is_degenerate() {
+            return scene.background.color(ray)
+        }
+        // Camera rays aren't unit length, so convert the distance into t along this one.
+        let capped = Ray { t_max: ray.t_max.min(self.distance / ray.dir.magnitude()), ..*ray };
+        self.inner.radiance(&capped, scene, depth)
+    }
+}
+
+// Renders with inner, but paints primary rays that hit a back face BACKFACE_COLOR.
+// Handy for spotting inverted geometry on surfaces that should be closed.
+pub struct BackfaceIntegrator<I: Integrator> {
+    pub inner: I,
+}
+
+impl<I: Integrator> Integrator for BackfaceIntegrator<I> {
+    fn radiance(&self, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64> {
+        match scene.objects.hit(ray, scene.bias.scatter, ray.t_max) {
+            Some(hit) if !hit.front_face => BACKFACE_COLOR,
+            _ => self.inner.radiance(ray, scene, depth),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Dielectric, Lambertian, Metal};
+    use crate::sphere::Sphere;
+
+    // A sphere 2 away from the camera in front of one 5 away, all under a graded sky.
+    fn two_spheres() -> HittableList {
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(-0.5, 0.0, -2.0), 0.5, Lambertian::new(Vector3::new(0.8, 0.1, 0.1))));
+        objects.push(Sphere::new(Vector3::new(1.5, 0.0, -5.0), 1.0, Lambertian::new(Vector3::new(0.1, 0.1, 0.8))));
+        objects
+    }
+
+    fn camera() -> Camera {
+        Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 60.0, 2.0, 0.0, 1.0)
+    }
+
+    #[test]
+    fn zero_distance_shows_only_the_background() {
+        let objects = two_spheres();
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let integrator = BackgroundIntegrator { inner: PathIntegrator::new(), distance: 0.0 };
+
+        let camera = camera();
+        for y in 0..10 {
+            for x in 0..20 {
+                let ray = camera.get_ray(x as f64 / 19.0, y as f64 / 9.0);
+                assert_eq!(integrator.radiance(&ray, &scene, 50), background.color(&ray));
+            }
+        }
+    }
+
+    #[test]
+    fn distance_hides_only_farther_objects() {
+        let objects = two_spheres();
+        let background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let integrator = BackgroundIntegrator { inner: NormalsIntegrator, distance: 3.0 };
+
+        let near = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(-0.5, 0.0, -2.0));
+        let far = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.5, 0.0, -5.0));
+        assert_ne!(integrator.radiance(&near, &scene, 50), Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(integrator.radiance(&far, &scene, 50), Vector3::new(0.0, 0.0, 0.0));
+
+        let everything = BackgroundIntegrator { inner: NormalsIntegrator, distance: f64::INFINITY };
+        assert_ne!(everything.radiance(&far, &scene, 50), Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn normals_of_a_front_facing_plus_z_hit() {
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+
+        // Straight down -z onto the sphere's near pole, where the normal is +z.
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert!(scene.objects.hit(&ray, RAY_BIAS, f64::INFINITY).unwrap().front_face);
+        assert_eq!(NormalsIntegrator.radiance(&ray, &scene, 50), Vector3::new(0.5, 0.5, 1.0));
+    }
+
+    // The book's ray_color, as main.rs had it before integrators.
+    fn book_ray_color(ray: &Ray, objects: &HittableList, depth: i32) -> Vector3<f64> {
+        if depth <= 0 {
+            return Vector3::<f64>::new(0.0, 0.0, 0.0);
+        }
+
+        if let Some(hit) = objects.hit(ray, 0.001, f64::MAX) {
+            if let Some((r, atten)) = hit.material.scatter(ray, &hit) {
+                return atten.zip(book_ray_color(&r, objects, depth-1), |l, r| l * r);
+            }
+            return Vector3::<f64>::new(0.0, 0.0, 0.0);
+        }
+        let t = 0.5 * (ray.dir.normalize().y + 1.0);
+        (1.0-t)*Vector3::new(1.0, 1.0, 1.0) + t*Vector3::new(0.5, 0.7, 1.0)
+    }
+
+    #[test]
+    fn path_integrator_is_the_book_ray_color() {
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, Lambertian::new(Vector3::new(0.8, 0.8, 0.0))));
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.1, 0.2, 0.5))));
+        objects.push(Sphere::new(Vector3::new(-1.0, 0.0, -1.0), 0.5, Dielectric::new(1.5)));
+        objects.push(Sphere::new(Vector3::new(1.0, 0.0, -1.0), 0.5, Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.3)));
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+
+        let camera = camera();
+        for y in 0..10 {
+            for x in 0..20 {
+                let ray = camera.get_ray(x as f64 / 19.0, y as f64 / 9.0);
+                crate::seed_rng(5, (y * 20 + x) as u64);
+                let path = PathIntegrator::new().radiance(&ray, &scene, 50);
+                crate::seed_rng(5, (y * 20 + x) as u64);
+                assert_eq!(path, book_ray_color(&ray, &objects, 50));
+            }
+        }
+    }
+
+    // Five glass balls in a row down -z, ten surfaces to get through, over a diffuse ground.
+    fn glass_panes() -> HittableList {
+        let mut objects = HittableList::new();
+        for i in 0..5 {
+            objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.5 - i as f64), 0.4, Dielectric::new(1.5)));
+        }
+        objects.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, Lambertian::new(Vector3::new(0.7, 0.7, 0.7))));
+        objects
+    }
+
+    // Mean radiance of ray over many differently seeded samples.
+    fn mean_radiance(integrator: &dyn Integrator, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64> {
+        let sum: Vector3<f64> = (0..2000).map(|i| {
+            crate::seed_rng(12, i);
+            integrator.radiance(ray, scene, depth)
+        }).sum();
+        sum / 2000.0
+    }
+
+    #[test]
+    fn transmission_and_diffuse_budgets_are_separate() {
+        let objects = glass_panes();
+        let background = Background::Solid(Vector3::new(1.0, 1.0, 1.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let budget = BounceBudget { diffuse: 2, transmission: 10, ..BounceBudget::unlimited() };
+
+        // Through the panes: ten transmissions see most of the sky, only paths that bounce
+        // around between the panes run out. Four only leave what reflects straight back.
+        let through = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        let deep = mean_radiance(&PathIntegrator::with_budget(budget), &through, &scene, 50).x;
+        let shallow = mean_radiance(&PathIntegrator::with_budget(BounceBudget { transmission: 4, ..budget }), &through, &scene, 50).x;
+        assert!(deep > 0.8, "{}", deep);
+        assert!(shallow < 0.3 * deep, "{} vs {}", shallow, deep);
+
+        // Without the panes only diffuse bounces are left, and the ground and ball between
+        // them stop after two: exactly what a depth limit of three gives.
+        let mut diffuse = HittableList::new();
+        diffuse.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, Lambertian::new(Vector3::new(0.7, 0.7, 0.7))));
+        diffuse.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.7, 0.7, 0.7))));
+        let scene = Scene { objects: &diffuse, ..scene };
+        let mut differs_from_deeper = false;
+        for i in 0..200 {
+            let down = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.3 - 0.003 * i as f64, -0.6, -1.0));
+            crate::seed_rng(13, i);
+            let budgeted = PathIntegrator::with_budget(budget).radiance(&down, &scene, 50);
+            crate::seed_rng(13, i);
+            assert_eq!(budgeted, PathIntegrator::new().radiance(&down, &scene, 3));
+            crate::seed_rng(13, i);
+            differs_from_deeper |= budgeted != PathIntegrator::new().radiance(&down, &scene, 50);
+        }
+        assert!(differs_from_deeper);
+    }
+
+    #[test]
+    fn zero_direction_rays_give_a_finite_color() {
+        let objects = glass_panes();
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        // From open air and from inside a glass ball.
+        for origin in [Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.5)].iter() {
+            let ray = Ray::new(*origin, Vector3::new(0.0, 0.0, 0.0));
+            assert_eq!(PathIntegrator::new().radiance(&ray, &scene, 50), Vector3::new(0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn backfaces_are_only_seen_from_inside() {
+        let background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+        let integrator = BackfaceIntegrator { inner: NormalsIntegrator };
+
+        // From outside only front faces are in view, hit or miss.
+        let objects = two_spheres();
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let camera = camera();
+        let mut hits = 0;
+        for y in 0..10 {
+            for x in 0..20 {
+                let ray = camera.get_ray(x as f64 / 19.0, y as f64 / 9.0);
+                hits += objects.hit(&ray, RAY_BIAS, f64::INFINITY).is_some() as i32;
+                assert_ne!(integrator.radiance(&ray, &scene, 50), BACKFACE_COLOR);
+            }
+        }
+        assert!(hits > 0);
+
+        // From inside a ball every direction sees its inner wall.
+        let mut inside = HittableList::new();
+        inside.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 10.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let scene = Scene { objects: &inside, ..scene };
+        for y in 0..10 {
+            for x in 0..20 {
+                let ray = camera.get_ray(x as f64 / 19.0, y as f64 / 9.0);
+                assert_eq!(integrator.radiance(&ray, &scene, 50), BACKFACE_COLOR);
+            }
+        }
+    }
+
+    // Variance of the red channel of ray over differently seeded samples.
+    fn radiance_variance(integrator: &dyn Integrator, ray: &Ray, scene: &Scene) -> f64 {
+        let samples: Vec<f64> = (0..500).map(|i| {
+            crate::seed_rng(14, i);
+            integrator.radiance(ray, scene, 50).x
+        }).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        samples.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn more_light_samples_smooth_shadow_edges() {
+        // A ball just over the ground, the ground under its edge partly shadowed.
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let background = Background::Solid(Vector3::new(1.0, 1.0, 1.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+
+        for x in [0.45, 0.55, 0.7].iter() {
+            let edge = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(*x, -0.5, -1.0));
+            let one = radiance_variance(&DirectIntegrator { light_samples: 1 }, &edge, &scene);
+            let sixteen = radiance_variance(&DirectIntegrator { light_samples: 16 }, &edge, &scene);
+            assert!(one > 0.0);
+            assert!(sixteen < 0.25 * one, "{} vs {}", sixteen, one);
+        }
+    }
+
+    #[test]
+    fn sphere_occlusion_sees_through_thin_surfaces() {
+        // The top of a huge ball stands in for a thin plane at y = 0, with a blocker just
+        // under it.
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, -1000.0, 0.0), 1000.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        objects.push(Sphere::new(Vector3::new(0.0, -0.5, 0.0), 0.3, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let above = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let below = Ray::new(Vector3::new(0.0, -0.1, 0.0), Vector3::new(0.0, 1.0, 0.0));
+
+        let ao = |sampling, ray| mean_radiance(&AmbientOcclusionIntegrator { distance: 1.0, sampling }, ray, &scene, 50).x;
+        // The hemisphere only looks out from the side it's seen from.
+        assert_eq!(ao(OcclusionSampling::Hemisphere, &above), 1.0);
+        assert!(ao(OcclusionSampling::Hemisphere, &below) < 0.9);
+        // The whole sphere finds the blocker from either side.
+        assert!(ao(OcclusionSampling::Sphere, &above) < 0.95);
+        assert!(ao(OcclusionSampling::Sphere, &below) < 0.95);
+    }
+
+    #[test]
+    fn shadow_bias_alone_clears_shadow_acne() {
+        // Nothing but one ball, so any occlusion on it is the ball shadowing itself.
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -20.0), 10.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let background = Background::Gradient { intensity: 1.0 };
+        let acne = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias { shadow: 0.0, ..RayBias::default() } };
+        let clean = Scene { bias: RayBias::default(), ..acne };
+        let ao = AmbientOcclusionIntegrator { distance: 100.0, sampling: OcclusionSampling::Hemisphere };
+        let direct = DirectIntegrator { light_samples: 1 };
+
+        let camera = camera();
+        let mut speckles = 0;
+        for y in 0..40 {
+            for x in 0..80 {
+                let ray = camera.get_ray(x as f64 / 79.0, y as f64 / 39.0);
+                crate::seed_rng(15, (y * 80 + x) as u64);
+                speckles += (ao.radiance(&ray, &acne, 50).x == 0.0) as i32;
+                crate::seed_rng(15, (y * 80 + x) as u64);
+                assert_eq!(ao.radiance(&ray, &clean, 50), Vector3::new(1.0, 1.0, 1.0));
+                crate::seed_rng(15, (y * 80 + x) as u64);
+                speckles += (direct.radiance(&ray, &acne, 50).x == 0.0) as i32;
+                crate::seed_rng(15, (y * 80 + x) as u64);
+                assert!(direct.radiance(&ray, &clean, 50).x > 0.0);
+
+                // Scattered rays only go by the scatter bias.
+                crate::seed_rng(15, (y * 80 + x) as u64);
+                let with_acne = PathIntegrator::new().radiance(&ray, &acne, 50);
+                crate::seed_rng(15, (y * 80 + x) as u64);
+                assert_eq!(with_acne, PathIntegrator::new().radiance(&ray, &clean, 50));
+            }
+        }
+        assert!(speckles > 0);
+    }
+
+    #[test]
+    fn bounce_ceiling_limits_specular_highlights_only() {
+        // A mirror under a hot sky.
+        let mut objects = HittableList::new();
+        let mirror_id = objects.push(Sphere::new(Vector3::new(-1.0, 0.0, -3.0), 0.8, Metal::new(Vector3::new(0.9, 0.9, 0.9), 0.0)));
+        let background = Background::Gradient { intensity: 4.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let ceiling = 1.5;
+
+        let mirror = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, -3.0));
+        crate::seed_rng(16, 0);
+        let highlight = PathIntegrator::new().radiance(&mirror, &scene, 50);
+        crate::seed_rng(16, 0);
+        let capped = PathIntegrator::new().with_bounce_ceiling(ceiling).radiance(&mirror, &scene, 50);
+        assert!(highlight.z > ceiling);
+        assert_eq!(capped, highlight.map(|c| c.min(ceiling)));
+
+        // A dull ball in its place never bounces back more than the ceiling. (Next to the
+        // mirror it would, wherever it sees the highlight in it.)
+        objects.replace(mirror_id, Sphere::new(Vector3::new(1.0, 0.0, -3.0), 0.8, Lambertian::new(Vector3::new(0.2, 0.2, 0.2))));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        for i in 0..100 {
+            let dull = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0 + 0.002 * i as f64, 0.0, -3.0));
+            crate::seed_rng(16, i);
+            let diffuse = PathIntegrator::new().radiance(&dull, &scene, 50);
+            crate::seed_rng(16, i);
+            assert_eq!(PathIntegrator::new().with_bounce_ceiling(ceiling).radiance(&dull, &scene, 50), diffuse);
+        }
+    }
+
+    #[test]
+    fn soft_cutoff_fades_long_paths_more_than_short_ones() {
+        let background = Background::Solid(Vector3::new(1.0, 1.0, 1.0));
+        let faded = PathIntegrator::new().with_soft_cutoff(2, 0.5);
+        let through = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        let kept = |objects: &HittableList| {
+            let scene = Scene { objects, background: &background, lighting: None, bias: RayBias::default() };
+            mean_radiance(&faded, &through, &scene, 50).x / mean_radiance(&PathIntegrator::new(), &through, &scene, 50).x
+        };
+
+        // Mostly straight through ten surfaces, faded eight times over. What reflects back
+        // early isn't faded, which keeps this well over 0.5^8.
+        let ten = kept(&glass_panes());
+        // Mostly straight through two, which is within the cutoff.
+        let mut one_ball = HittableList::new();
+        one_ball.push(Sphere::new(Vector3::new(0.0, 0.0, -1.5), 0.4, Dielectric::new(1.5)));
+        let two = kept(&one_ball);
+        assert!(two > 0.9, "{}", two);
+        assert!(ten < 0.1 * two, "{} vs {}", ten, two);
+    }
+
+    #[test]
+    fn camera_misses_see_the_backdrop_and_bounces_the_lighting() {
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -2.0), 0.5, Metal::new(Vector3::new(1.0, 1.0, 1.0), 0.0)));
+        let backdrop = Background::Solid(Vector3::new(1.0, 0.0, 0.0));
+        let lighting = Background::Solid(Vector3::new(0.0, 0.0, 1.0));
+        let scene = Scene { objects: &objects, background: &backdrop, lighting: Some(&lighting), bias: RayBias::default() };
+
+        let miss = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, -1.0));
+        assert_eq!(scene.miss(&miss, true), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(scene.miss(&miss, false), Vector3::new(0.0, 0.0, 1.0));
+
+        // Off the mirror is a bounced miss.
+        let mirror = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+        assert_eq!(PathIntegrator::new().radiance(&miss, &scene, 50), Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(PathIntegrator::new().radiance(&mirror, &scene, 50), Vector3::new(0.0, 0.0, 1.0));
+    }
+}
@@ -1,6 +1,8 @@
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use cgmath::InnerSpace;
 use cgmath::Vector3;
+use std::cell::RefCell;
 
 pub mod ray;
 pub mod hittable;
@@ -8,15 +10,71 @@ pub mod sphere;
 pub mod hittable_list;
 pub mod camera;
 pub mod material;
+pub mod distribution;
+pub mod background;
+pub mod texture;
+pub mod scenes;
+pub mod integrator;
+pub mod alpha_cutout;
+pub mod reference;
+pub mod render;
+pub mod streaming;
+pub mod spectrum;
 
-static PI: f64 = 3.1415926535897932385;
+static PI: f64 = std::f64::consts::PI;
+
+// The parallel renderer shares the scene between rayon threads, so everything in it has to be
+// Send + Sync. This stops compiling if something like a RefCell cache sneaks into one of them.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync + ?Sized>() {}
+    assert_send_sync::<dyn hittable::Hittable>();
+    assert_send_sync::<hittable_list::HittableList>();
+    assert_send_sync::<dyn material::Material>();
+    assert_send_sync::<dyn texture::Texture>();
+    assert_send_sync::<background::Background>();
+    assert_send_sync::<camera::Camera>();
+    assert_send_sync::<dyn integrator::Integrator>();
+    assert_send_sync::<integrator::Scene<'static>>();
+};
+
+thread_local! {
+    static RNG: RefCell<ChaCha8Rng> = RefCell::new(ChaCha8Rng::from_entropy());
+}
+
+// Run f with this thread's sampling RNG. Every random helper draws from it,
+// so f mustn't call back into them.
+pub fn with_rng<T, F: FnOnce(&mut ChaCha8Rng) -> T>(f: F) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+// Restart this thread's RNG on stream number stream of seed.
+// ChaCha is counter based and its streams never overlap, so giving every tile of work its
+// own stream makes a render reproducible no matter which thread picks the tile up,
+// without the correlation you risk by seeding each tile with its own nearby seed.
+pub fn seed_rng(seed: u64, stream: u64) {
+    RNG.with(|rng| {
+        let mut seeded = ChaCha8Rng::seed_from_u64(seed);
+        seeded.set_stream(stream);
+        *rng.borrow_mut() = seeded;
+    })
+}
+
+// The book's ray_color: what a ray sees, bouncing through scene up to depth times.
+// Shorthand for the default PathIntegrator, for tools that just want a color for a ray.
+pub fn ray_color(ray: &ray::Ray, scene: &integrator::Scene, depth: i32) -> Vector3<f64> {
+    use integrator::Integrator;
+    integrator::PathIntegrator::new().radiance(ray, scene, depth)
+}
 
 pub fn random_unit_vector() -> Vector3<f64> {
     random_in_unit_sphere().normalize()
 }
 
 pub fn random_in_unit_sphere() -> Vector3<f64> {
-    let mut rng = rand::thread_rng();
+    with_rng(sample_in_unit_sphere)
+}
+
+pub fn sample_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Vector3<f64> {
     let unit = Vector3::new(1.0, 1.0, 1.0);
     loop {
         let p = 2.0 * Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()) - unit;
@@ -27,21 +85,18 @@ pub fn random_in_unit_sphere() -> Vector3<f64> {
 }
 
 pub fn random_double() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(0.0, 1.0)
+    with_rng(|rng| rng.gen_range(0.0, 1.0))
 }
 
 pub fn random_color() -> Vector3<f64> {
-    let mut rng = rand::thread_rng();
-    Vector3{ x: rng.gen_range(0.0, 1.0), y: rng.gen_range(0.0, 1.0), z: rng.gen_range(0.0, 1.0) }
+    with_rng(|rng| Vector3{ x: rng.gen_range(0.0, 1.0), y: rng.gen_range(0.0, 1.0), z: rng.gen_range(0.0, 1.0) })
 }
 
 fn random_in_unit_disk() -> Vector3<f64> {
-    let mut rng = rand::thread_rng();
-    loop {
+    with_rng(|rng| loop {
         let p = Vector3 { x: rng.gen_range(-1.0, 1.0), y: rng.gen_range(-1.0, 1.0), z: 0.0 };
         if p.magnitude2() < 1.0 { return p; }
-    }
+    })
 }
 
 fn deg_to_rad(degrees: f64) -> f64 {
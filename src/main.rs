@@ -5,26 +5,29 @@
 
 use raytracing::random_double;
 use raytracing::random_color;
-use raytracing::hittable::Hittable;
-use raytracing::camera::Camera;
+use raytracing::hittable::{Hittable, BACKGROUND_ID};
+use raytracing::background::Background;
+use raytracing::camera::{image_height, Camera, CoordinateSystem, Handedness, UpAxis};
+use raytracing::integrator::*;
 use cgmath::*;
-use raytracing::ray::*;
 use raytracing::sphere::*;
 use raytracing::hittable_list::*;
 use raytracing::material::*;
-use rand::*;
+use raytracing::ray::Ray;
+use raytracing::render::*;
 use rayon::prelude::*;
+use std::io::Write;
+use std::sync::atomic::Ordering;
 
-static PI: f64 = 3.1415926535897932385;
+static PI: f64 = std::f64::consts::PI;
 static INFINITY: f64 = f64::MAX;
-
-
 // cargo run > img.ppm
 fn main() {
     // Image
     let aspect_ratio = 3.0 / 2.0;
+    // 2.0 for a 2:1 anamorphic squeeze, see RenderSettings.
+    let pixel_aspect_ratio = 1.0;
     let img_width = 600;
-    let img_height = (img_width as f64/aspect_ratio) as i32;
 
     // Number of "nearby" colors to get an average of for accurate color
     // Antialiasing
@@ -32,12 +35,15 @@ fn main() {
     // Number of rays to shoot per reflection.
     let max_depth = 50;
 
+    // Up axis and handedness the scene was authored with. The camera below and the scene
+    // builders convert everything they place into the renderer's Y-up, right-handed space.
+    let coordinates = CoordinateSystem { up: UpAxis::Y, handedness: Handedness::Right };
     // Where our camera is located
-    let look_from = Vector3 { x: 13.0, y: 2.0, z: 3.0 };
+    let look_from = coordinates.to_world(Vector3 { x: 13.0, y: 2.0, z: 3.0 });
     // What our camera is looking at
-    let look_at = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+    let look_at = coordinates.to_world(Vector3 { x: 0.0, y: 0.0, z: 0.0 });
     // Our camera's "up"
-    let vup = Vector3 { x: 0.0, y: 1.0, z: 0.0 };
+    let vup = coordinates.up();
     // Distance that is in focus
     let dist_to_focus = 10.0;
     // Size of the "disk" we shoot rays out from for blurring
@@ -51,147 +57,309 @@ fn main() {
                              aspect_ratio,
                              aperture,
                              dist_to_focus);
+    // Ignore geometry nearer than the first or farther than the second distance, for cutaways.
+    //let camera = camera.with_clipping(5.0, 20.0);
+    // 0.0 is perspective, 1.0 orthographic, in between with some aperture for a tilt-shift look.
+    //let camera = camera.with_projection_blend(0.5);
 
     // World
     let mut objects = HittableList::new();
+    // What rays that miss everything see.
+    let background = Background::Gradient { intensity: 1.0 };
+
+    let settings = RenderSettings {
+        img_width,
+        img_height: image_height(img_width, aspect_ratio, pixel_aspect_ratio),
+        pixel_aspect_ratio,
+        samples_per_pixel,
+        max_depth,
+        lens_samples: 1,
+        // --bit-depth 16 on the command line for 16-bit PPM.
+        bit_depth: bit_depth_from_args(),
+        output_format: OutputFormat::Ppm,
+        // ToneMap::Reinhard { white: 4.0 } rolls highlights off instead of clipping them.
+        tone_map: ToneMap::Clamp,
+        filter: Filter::Box { radius: 0.5 },
+        jitter: true,
+        shard: shard_from_args(),
+        // rand::random() for a different noise pattern every run.
+        seed: 0,
+    };
 
     let mut timer_start = std::time::Instant::now();
 
-    //generate_v1_world(&mut objects); // 10 seconds down to ~2 when parallel.
-    //generate_v2_world(&mut objects);// 6 seconds down to ~2 when parallel
-    random_scene(&mut objects);
+    // The random scene is reproducible too, on a stream no render tile uses.
+    raytracing::seed_rng(settings.seed, u64::MAX);
+    //generate_v1_world(&mut objects, coordinates); // 10 seconds down to ~2 when parallel.
+    //generate_v2_world(&mut objects, coordinates);// 6 seconds down to ~2 when parallel
+    random_scene(&mut objects, coordinates);
+    // The v1 world with a checkered ground, in place of the above.
+    //let objects = raytracing::scenes::default_with_checker_ground(coordinates);
+    // Energy conservation check, the sphere should disappear into the background.
+    //let (objects, background) = raytracing::scenes::furnace(1.0);
 
     let mut time_taken = std::time::Instant::now().duration_since(timer_start);
 
     eprintln!("World Generation Time: {:?}m{:?}s", time_taken.as_secs()/60, time_taken.as_secs()%60);
 
-    println!("P3\n{} {}\n255", img_width, img_height);
-
     timer_start = std::time::Instant::now();
 
-    //render(img_height, img_width, samples_per_pixel, camera, objects, max_depth);
-    render_par(img_height, img_width, samples_per_pixel, &camera, &objects, max_depth);
+    install_cancel_handler();
+
+    // A camera inside a closed object only sees that object's inside wall.
+    // skip_enclosing lets camera rays pass through it instead, e.g. to look out of a glass dome.
+    let skip_enclosing = false;
+    let camera = match objects.enclosing(look_from) {
+        Some(id) if skip_enclosing => camera.with_skipped_object(id),
+        Some(id) => {
+            eprintln!("Camera is inside object {}, set skip_enclosing to see out of it", id);
+            camera
+        }
+        None => camera,
+    };
+
+    // lighting: Some(&sky) lights the scene with a different background than the camera sees.
+    let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+    // How each camera ray gets turned into a color.
+    // Also: AmbientOcclusionIntegrator { distance: 1.0, sampling: OcclusionSampling::Hemisphere }, NormalsIntegrator, DirectIntegrator { light_samples: 1 },
+    // BackfaceIntegrator { inner: PathIntegrator::new() } to spot inverted geometry,
+    // and BackgroundIntegrator { inner: PathIntegrator::new(), distance: 0.0 } to look at the background
+    // with the objects hidden, or with only those nearer than distance.
+    // The default is the library's ray_color, the same as PathIntegrator::new().
+    // PathIntegrator::with_budget(BounceBudget { .. }) limits each kind of bounce separately,
+    // .with_bounce_ceiling(c) caps how bright any one bounce can be,
+    // .with_soft_cutoff(n, f) fades paths by f for every bounce after the nth.
+    let integrator = RayColor;
+
+    //render(settings, camera, &scene, &integrator);
+    // Object id matte instead of the beauty render. Pass Some(id) for a single object's mask.
+    //render_matte_par(&settings, &camera, &objects, scene.bias, None);
+    // Quarter size preview to preview.ppm first, for checking the framing.
+    //render_preview_par(&settings, &camera, &scene, &integrator);
+    render_par(&settings, &camera, &scene, &integrator, &mut std::io::stdout());
 
     time_taken = std::time::Instant::now().duration_since(timer_start);
 
     eprintln!("Done! Render Time: {:?}m{:?}s", time_taken.as_secs()/60, time_taken.as_secs()%60);
 }
 
-fn render(  img_height: i32, 
-            img_width: i32, 
-            samples_per_pixel: i32, 
+// raytracing::ray_color as an integrator, so the binary renders with the library's own.
+struct RayColor;
+
+impl Integrator for RayColor {
+    fn radiance(&self, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64> {
+        raytracing::ray_color(ray, scene, depth)
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigint(_: libc::c_int) {
+    CANCELLED.store(true, Ordering::SeqCst);
+    // A second Ctrl-C kills the process as usual.
+    unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL); }
+}
+
+// Ctrl-C stops the render early and still writes out the samples taken so far.
+fn install_cancel_handler() {
+    #[cfg(unix)]
+    unsafe { libc::signal(libc::SIGINT, on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t); }
+}
+
+fn render(  settings: RenderSettings,
             camera: Camera, 
-            objects: HittableList, 
-            max_depth: i32) {
+            scene: &Scene,
+            integrator: &dyn Integrator) {
 
-    for y in (0..img_height).rev() {
+    let mut image = Vec::<Vector3<f64>>::new();
+
+    for y in (0..settings.img_height).rev() {
         eprintln!("Scanlines remaining: {}", y);
-            for x in 0..img_width {
-                let mut sampled_pixel = Vector3::<f64>::new(0.0, 0.0, 0.0);
-                // Antialiasing: The edges of a pixel should be the "average" of colors around it.
-                for _ in 0..samples_per_pixel {
-                    let u: f64 = (x  as f64 + random_double())/(img_width-1) as f64;
-                    let v: f64 = (y  as f64 + random_double())/(img_height-1) as f64;
-        
-                    let r = camera.get_ray(u, v);
-        
-                    sampled_pixel += ray_color(&r, &objects, max_depth);
-                }
-    
-                write_color(sampled_pixel, samples_per_pixel as f64);
+            raytracing::seed_rng(settings.seed, (settings.img_height - 1 - y) as u64);
+            for x in 0..settings.img_width {
+                image.push(sample_pixel(&settings, &camera, scene, integrator, x, y) / settings.samples_per_pixel as f64);
             }
         }
+
+    write_image(&settings, &image, &mut std::io::stdout());
 }
 
-fn render_par(  img_height: i32, 
-    img_width: i32, 
-    samples_per_pixel: i32, 
+fn render_par(  settings: &RenderSettings,
     camera: &Camera, 
-    objects: &HittableList, 
-    max_depth: i32) {
-
-        let image: Vec<Vector3<f64>> = (0..img_height).into_par_iter().rev().flat_map(|y| {
-            (0..img_width).into_par_iter().map(move |x| {
-                let sampled_pixel = (0..samples_per_pixel).map(move |_| {
-                    let u: f64 = (x  as f64 + random_double())/(img_width-1) as f64;
-                    let v: f64 = (y  as f64 + random_double())/(img_height-1) as f64;
-        
-                    let r = camera.get_ray(u, v);
-                    ray_color(&r, &objects, max_depth)
-                }).sum();
-                sampled_pixel
-            })
-        }).collect();
-
-        // We've collected all colors into a list of vector3, print them.
-        for col in image {
-            write_color(col, samples_per_pixel as f64);
+    scene: &Scene,
+    integrator: &dyn Integrator,
+    out: &mut dyn Write) {
+
+        // A shard is written as an image of just its band. Joining the pixel data of every
+        // shard in order (PFM: in reverse, its rows go bottom up) gives the full image.
+        let band = RenderSettings { img_height: settings.shard_rows().len() as i32, ..*settings };
+        write_image(&band, &trace_par(settings, camera, scene, integrator), out);
+}
+
+// --shard i/n on the command line picks the band to render, see RenderSettings::shard.
+fn shard_from_args() -> (u32, u32) {
+    let args: Vec<String> = std::env::args().collect();
+    let value = match args.iter().position(|arg| arg == "--shard") {
+        Some(i) => args.get(i + 1),
+        None => return (0, 1),
+    };
+
+    let parsed = value.and_then(|value| {
+        let (index, count) = value.split_once('/')?;
+        Some((index.parse::<u32>().ok()?, count.parse::<u32>().ok()?))
+    });
+    match parsed {
+        Some((index, count)) if index < count => (index, count),
+        _ => {
+            eprintln!("--shard takes i/n with i < n, e.g. --shard 0/4");
+            std::process::exit(2);
+        }
+    }
+}
+
+// --bit-depth 8 or --bit-depth 16 on the command line, see RenderSettings::bit_depth.
+fn bit_depth_from_args() -> u32 {
+    let args: Vec<String> = std::env::args().collect();
+    let value = match args.iter().position(|arg| arg == "--bit-depth") {
+        Some(i) => args.get(i + 1),
+        None => return 8,
+    };
+
+    match value.and_then(|value| parse_bit_depth(value)) {
+        Some(bit_depth) => bit_depth,
+        None => {
+            eprintln!("--bit-depth takes 8 or 16, e.g. --bit-depth 16");
+            std::process::exit(2);
         }
+    }
 }
 
-fn write_color(color: Vector3<f64>, samples_per_pixel: f64) {
-    let mut r = color.x;
-    let mut g = color.y;
-    let mut b = color.z;
+fn parse_bit_depth(value: &str) -> Option<u32> {
+    match value.parse::<u32>() {
+        Ok(bit_depth) if bit_depth == 8 || bit_depth == 16 => Some(bit_depth),
+        _ => None,
+    }
+}
 
-    // Divide color by number of samples
-    let scale = 1.0 / samples_per_pixel;
-    r = (r * scale).sqrt();
-    g = (g * scale).sqrt();
-    b = (b * scale).sqrt();
+// A quick look at the composition before committing to the full render: a quarter of the
+// size and a handful of samples, written to preview.ppm. The tiles are seeded the same
+// either way, so the full render that follows is identical to one without a preview.
+fn render_preview_par(settings: &RenderSettings,
+    camera: &Camera,
+    scene: &Scene,
+    integrator: &dyn Integrator) {
+
+        let preview = settings.preview();
+        let image = trace_par(&preview, camera, scene, integrator);
+        let file = std::fs::File::create("preview.ppm").expect("failed to create preview.ppm");
+        write_image(&preview, &image, &mut std::io::BufWriter::new(file));
+        eprintln!("Preview written to preview.ppm");
+}
 
-    println!("{} {} {}", (256.0 * clamp(r, 0.0, 0.999)) as i32, (256.0 * clamp(g, 0.0, 0.999)) as i32, (256.0 * clamp(b, 0.0, 0.999)) as i32);
+// Sum of samples_per_pixel samples for the pixel at (x, y), y counting up from the bottom.
+fn sample_pixel(settings: &RenderSettings, camera: &Camera, scene: &Scene, integrator: &dyn Integrator, x: i32, y: i32) -> Vector3<f64> {
+    // Antialiasing: The edges of a pixel should be the "average" of colors around it.
+    (0..settings.samples_per_pixel).map(|_| sample(settings, camera, scene, integrator, x, y)).sum()
 }
 
-fn ray_color(ray: &Ray, drawables: &HittableList, depth: i32) -> Vector3<f64> {
+// One antialiasing sample somewhere inside the pixel at (x, y).
+fn sample(settings: &RenderSettings, camera: &Camera, scene: &Scene, integrator: &dyn Integrator, x: i32, y: i32) -> Vector3<f64> {
+    sample_at(settings, camera, scene, integrator, x as f64 + settings.pixel_offset(), y as f64 + settings.pixel_offset())
+}
 
-    // Don't let the stack overflow
-    if depth <= 0 {
-        return Vector3::<f64>::new(0.0, 0.0, 0.0);
+// image holds each pixel's averaged color, top row first.
+fn write_image(settings: &RenderSettings, image: &[Vector3<f64>], out: &mut dyn Write) {
+    match settings.output_format {
+        OutputFormat::Ppm => {
+            // We've collected all colors into a list of vector3, print them.
+            writeln!(out, "P3\n{} {}\n{}", settings.img_width, settings.img_height, settings.max_value()).expect("failed to write PPM");
+            for col in image {
+                write_color(out, settings.tone_map.apply(*col), settings.max_value());
+            }
+        }
+        OutputFormat::Pfm => {
+            // Negative scale marks the floats as little endian. Rows go bottom to top.
+            let mut bytes = format!("PF\n{} {}\n-1.0\n", settings.img_width, settings.img_height).into_bytes();
+            for row in image.chunks(settings.img_width as usize).rev() {
+                for color in row {
+                    for channel in [color.x, color.y, color.z].iter() {
+                        bytes.extend_from_slice(&(*channel as f32).to_le_bytes());
+                    }
+                }
+            }
+            out.write_all(&bytes).expect("failed to write PFM");
+        }
     }
+}
 
-    if let Some(hit) = drawables.hit(ray, 0.001, INFINITY) {
-        if let Some((r, atten)) = hit.material.scatter(&ray, &hit) {
-            return atten.zip(ray_color(&r, &drawables, depth-1), |l, r| l * r);
+// Writes a matte pass: each pixel holds the id of the first object its primary ray hits,
+// or BACKGROUND_ID. With a selected id it's a white-on-black mask of just that object.
+// bias should be the beauty render's, so the matte lines up with it.
+fn render_matte_par(settings: &RenderSettings,
+    camera: &Camera,
+    objects: &HittableList,
+    bias: RayBias,
+    selected: Option<usize>) {
+
+        let RenderSettings { img_width, img_height, .. } = *settings;
+        let ids = trace_matte_par(settings, camera, objects, bias);
+
+        // Grayscale PGM, so the max value has to cover every id in the list.
+        let max_value = if selected.is_some() { 1 } else { objects.max_id().max(1) };
+        println!("P2\n{} {}\n{}", img_width, img_height, max_value);
+        for id in ids {
+            match selected {
+                Some(selected_id) => println!("{}", if id == selected_id { 1 } else { 0 }),
+                None => println!("{}", id),
+            }
         }
-        return Vector3::<f64>::new(0.0, 0.0, 0.0);
-    }
-    get_background_color(&ray)
 }
 
-fn get_background_color(ray: &Ray) -> Vector3<f64> {
-        // Normalize vector so we have y between -1 and 1.
-        let unit_dir = ray.dir.normalize();
-
-        // Add 1 to y so y's bounds are [0.0, 2.0]
-        // Multiply that by 0.5 so the bounds are [0.0, 1.0]
-        // Let t be the scale (from [0.0, 1.0]) of white or blue.
-        let t = 0.5 * (unit_dir.y + 1.0);
-    
-        // When t is 1 (max height), the first segment
-        // of addition is (1.0 - 1.0)*white so no white is produced at top
-        // When t is 0 (min height), second segment becomes 
-        // 0*blue (no blue produced at bottom of image)
-        // In other words, linear interpolation.
-        (1.0-t)*Vector3::new(1.0, 1.0, 1.0) + t*Vector3::new(0.5, 0.7, 1.0)
+// Id of the first object hit by each pixel's center ray, top row first.
+fn trace_matte_par(settings: &RenderSettings, camera: &Camera, objects: &HittableList, bias: RayBias) -> Vec<usize> {
+    let RenderSettings { img_width, img_height, .. } = *settings;
+
+    (0..img_height).into_par_iter().rev().flat_map(|y| {
+        (0..img_width).into_par_iter().map(move |x| {
+            let u: f64 = (x as f64 + 0.5)/(img_width-1) as f64;
+            let v: f64 = (y as f64 + 0.5)/(img_height-1) as f64;
+
+            let r = camera.get_ray(u, v);
+            match objects.hit(&r, bias.scatter, r.t_max) {
+                Some(hit) => hit.object_id,
+                None => BACKGROUND_ID,
+            }
+        })
+    }).collect()
+}
+
+// color is already averaged over the pixel's samples.
+fn write_color(out: &mut dyn Write, color: Vector3<f64>, max_value: i32) {
+    // Gamma correct for gamma 2
+    let r = color.x.sqrt();
+    let g = color.y.sqrt();
+    let b = color.z.sqrt();
+
+    // Gamma is applied above in floating point, so 16-bit output gets the full precision.
+    let levels = (max_value + 1) as f64;
+    writeln!(out, "{} {} {}", (levels * clamp(r, 0.0, 0.99999)) as i32, (levels * clamp(g, 0.0, 0.99999)) as i32, (levels * clamp(b, 0.0, 0.99999)) as i32).expect("failed to write PPM");
 }
 
 fn clamp(x: f64, min: f64, max: f64) -> f64 {
-    return if x < min { min } else if x > max { max } else { x }
+    if x < min { min } else if x > max { max } else { x }
 }
 
-fn generate_v1_world(objects: &mut HittableList) {
+fn generate_v1_world(objects: &mut HittableList, coordinates: CoordinateSystem) {
     let ground_material = Lambertian::new(Vector3 { x: 0.8, y: 0.8, z: 0.0 });
     let center_material = Lambertian::new(Vector3 { x: 0.1, y: 0.2, z: 0.5 });
     let left_material = Dielectric::new(1.5);
     let left_inner_material = Dielectric::new(1.5);
     let right_material = Metal::new(Vector3 { x: 0.8, y: 0.6, z: 0.2 }, 0.0);
 
-    let ground_sphere = Sphere::new(Vector3 { x: 0.0, y: -100.5, z: -1.0 }, 100.0, ground_material);
-    let center_sphere = Sphere::new(Vector3 { x: 0.0, y: 0.0, z: -1.0 }, 0.5, center_material);
-    let left_sphere = Sphere::new(Vector3 { x: -1.0, y: 0.0, z: -1.0 }, 0.5, left_material);
-    let left_inner_sphere = Sphere::new(Vector3 { x: -1.0, y: 0.0, z: -1.0 }, -0.4, left_inner_material);
-    let right_sphere = Sphere::new(Vector3 { x: 1.0, y: 0.0, z: -1.0 }, 0.5, right_material);
+    let ground_sphere = Sphere::new(coordinates.to_world(Vector3 { x: 0.0, y: -100.5, z: -1.0 }), 100.0, ground_material);
+    let center_sphere = Sphere::new(coordinates.to_world(Vector3 { x: 0.0, y: 0.0, z: -1.0 }), 0.5, center_material);
+    let left_sphere = Sphere::new(coordinates.to_world(Vector3 { x: -1.0, y: 0.0, z: -1.0 }), 0.5, left_material);
+    let left_inner_sphere = Sphere::new(coordinates.to_world(Vector3 { x: -1.0, y: 0.0, z: -1.0 }), -0.4, left_inner_material);
+    let right_sphere = Sphere::new(coordinates.to_world(Vector3 { x: 1.0, y: 0.0, z: -1.0 }), 0.5, right_material);
 
     objects.push(ground_sphere);
     objects.push(center_sphere);
@@ -200,36 +368,35 @@ fn generate_v1_world(objects: &mut HittableList) {
     objects.push(right_sphere);
 }
 
-fn generate_v2_world(objects: &mut HittableList) {
+fn generate_v2_world(objects: &mut HittableList, coordinates: CoordinateSystem) {
     let radius = (crate::PI/4.0).cos();
     let right_material = Lambertian::new(Vector3 { x: 0.0, y: 0.0, z: 1.0 });
-    let right_sphere = Sphere::new(Vector3 { x: -radius, y: 0.0, z: -1.0 }, radius, right_material);
+    let right_sphere = Sphere::new(coordinates.to_world(Vector3 { x: -radius, y: 0.0, z: -1.0 }), radius, right_material);
 
     let left_material = Lambertian::new(Vector3 { x: 1.0, y: 0.0, z: 0.0 });
-    let left_sphere = Sphere::new(Vector3 { x: radius, y: 0.0, z: -1.0 }, radius, left_material);
+    let left_sphere = Sphere::new(coordinates.to_world(Vector3 { x: radius, y: 0.0, z: -1.0 }), radius, left_material);
 
     objects.push(left_sphere);
     objects.push(right_sphere);
 }
 
-fn random_scene(objects: &mut HittableList) {
-    let mut rng = rand::thread_rng();
+fn random_scene(objects: &mut HittableList, coordinates: CoordinateSystem) {
     let origin = Vector3 { x: 4.0, y: 0.2, z: 0.0 };
     let ground_material = Lambertian::new(Vector3 { x: 0.5, y: 0.5, z: 0.5 });
-    let world = Sphere::new(Vector3 { x: 0.0, y: -1000.0, z: 0.0 }, 1000.0, ground_material);
+    let world = Sphere::new(coordinates.to_world(Vector3 { x: 0.0, y: -1000.0, z: 0.0 }), 1000.0, ground_material);
     objects.push(world);
 
     for a in -11..12 {
         for b in -11..12 {
-            let material_to_use = rng.gen_range(0.0, 1.0);
-            let center = Vector3 { x: a as f64 + 0.9*rng.gen_range(0.0, 1.0), y: 0.2, z: b as f64 + 0.9*rng.gen_range(0.0, 1.0) };
+            let material_to_use = random_double();
+            let center = Vector3 { x: a as f64 + 0.9*random_double(), y: 0.2, z: b as f64 + 0.9*random_double() };
 
             if (center - origin).magnitude() > 0.9 {
                 if material_to_use < 0.8 { // Diffuse
                     eprintln!("Diffuse was chosen");
                     let albedo = random_color();
                     let sphere_material = Lambertian::new(albedo);
-                    let sphere = Sphere::new(center, 0.2, sphere_material);
+                    let sphere = Sphere::new(coordinates.to_world(center), 0.2, sphere_material);
                     objects.push(sphere);
                 }
                 else if material_to_use < 0.95 {
@@ -237,13 +404,13 @@ fn random_scene(objects: &mut HittableList) {
                     let albedo = random_color();
                     let fuzz = random_double();
                     let sphere_material = Metal::new(albedo, fuzz);
-                    let sphere = Sphere::new(center, 0.2, sphere_material);
+                    let sphere = Sphere::new(coordinates.to_world(center), 0.2, sphere_material);
                     objects.push(sphere);
                 }
                 else {
                     eprintln!("Dielectric was chosen");
                     let sphere_material = Dielectric::new(1.5);
-                    let sphere = Sphere::new(center, 0.2, sphere_material);
+                    let sphere = Sphere::new(coordinates.to_world(center), 0.2, sphere_material);
                     objects.push(sphere);
                 }
             }
@@ -254,11 +421,400 @@ fn random_scene(objects: &mut HittableList) {
     let material2 = Lambertian::new(Vector3{x: 0.4, y: 0.2, z: 0.1});
     let material3 = Metal::new(Vector3{x: 0.7, y: 0.6, z: 0.5}, 0.0);
 
-    let sphere1 = Sphere::new(Vector3{x: 0.0, y: 1.0, z: 0.0}, 1.0, material1);
-    let sphere2 = Sphere::new(Vector3{x: -4.0, y: 1.0, z: 0.0}, 1.0, material2);
-    let sphere3 = Sphere::new(Vector3{x: 4.0, y: 1.0, z: 0.0}, 1.0, material3);
+    let sphere1 = Sphere::new(coordinates.to_world(Vector3{x: 0.0, y: 1.0, z: 0.0}), 1.0, material1);
+    let sphere2 = Sphere::new(coordinates.to_world(Vector3{x: -4.0, y: 1.0, z: 0.0}), 1.0, material2);
+    let sphere3 = Sphere::new(coordinates.to_world(Vector3{x: 4.0, y: 1.0, z: 0.0}), 1.0, material3);
 
     objects.push(sphere1);
     objects.push(sphere2);
     objects.push(sphere3);
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small and quick, but with enough samples and bounces to exercise the materials.
+    fn test_settings(img_width: i32, img_height: i32) -> RenderSettings {
+        RenderSettings::new(img_width, img_height, 4, 8, 7)
+    }
+
+    fn test_camera(look_from: Vector3<f64>, look_at: Vector3<f64>, vup: Vector3<f64>) -> Camera {
+        Camera::new(look_from, look_at, vup, 40.0, 2.0, 0.0, 1.0)
+    }
+
+    fn trace(settings: &RenderSettings, camera: &Camera, objects: &HittableList) -> Vec<Vector3<f64>> {
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects, background: &background, lighting: None, bias: RayBias::default() };
+        trace_par(settings, camera, &scene, &PathIntegrator::new())
+    }
+
+    fn max_difference(a: &[Vector3<f64>], b: &[Vector3<f64>]) -> f64 {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b.iter()).map(|(a, b)| (a - b).map(f64::abs).sum()).fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn z_up_scene_renders_like_the_rotated_y_up_scene() {
+        // A Y-up world turned on its side: -90 degrees about x takes Z-up to Y-up.
+        let rotation = Matrix3::from_angle_x(Deg(-90.0));
+        let z_up_look_from = Vector3::new(3.0, -1.0, 0.5);
+        let z_up_look_at = Vector3::new(-0.5, 0.0, -1.0);
+        let settings = test_settings(24, 12);
+
+        let z_up = CoordinateSystem { up: UpAxis::Z, handedness: Handedness::Right };
+        let z_up_world = {
+            let mut objects = HittableList::new();
+            objects.push(Sphere::new(z_up.to_world(Vector3::new(0.0, 1.0, -100.5)), 100.0, Lambertian::new(Vector3::new(0.8, 0.8, 0.0))));
+            objects.push(Sphere::new(z_up.to_world(Vector3::new(-1.0, 1.0, 0.0)), 0.5, Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.0)));
+            objects.push(Sphere::new(z_up.to_world(Vector3::new(0.5, 0.0, 0.0)), 0.5, Lambertian::new(Vector3::new(0.1, 0.2, 0.5))));
+            objects
+        };
+        let converted = trace(&settings, &test_camera(z_up.to_world(z_up_look_from), z_up.to_world(z_up_look_at), z_up.up()), &z_up_world);
+
+        let rotated_world = {
+            let mut objects = HittableList::new();
+            objects.push(Sphere::new(rotation * Vector3::new(0.0, 1.0, -100.5), 100.0, Lambertian::new(Vector3::new(0.8, 0.8, 0.0))));
+            objects.push(Sphere::new(rotation * Vector3::new(-1.0, 1.0, 0.0), 0.5, Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.0)));
+            objects.push(Sphere::new(rotation * Vector3::new(0.5, 0.0, 0.0), 0.5, Lambertian::new(Vector3::new(0.1, 0.2, 0.5))));
+            objects
+        };
+        let rotated = trace(&settings, &test_camera(rotation * z_up_look_from, rotation * z_up_look_at, rotation * Vector3::new(0.0, 0.0, 1.0)), &rotated_world);
+
+        assert!(max_difference(&converted, &rotated) < 1e-9);
+        // Upright: the ground is at the bottom of the frame and the sky at the top.
+        let top = converted[..24].iter().sum::<Vector3<f64>>();
+        let bottom = converted[converted.len() - 24..].iter().sum::<Vector3<f64>>();
+        assert!(top.z > top.x && bottom.x > bottom.z);
+    }
+
+    #[test]
+    fn builders_place_geometry_through_the_coordinate_system() {
+        let z_up = CoordinateSystem { up: UpAxis::Z, handedness: Handedness::Right };
+        let mut objects = HittableList::new();
+        generate_v1_world(&mut objects, z_up);
+        // Read as Z-up, the v1 world's center sphere at (0, 0, -1) is one unit down.
+        let down = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        let hit = objects.hit(&down, 0.001, f64::INFINITY).expect("center sphere should be below");
+        assert!((hit.t - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn anamorphic_pixels_squeeze_a_circle_into_a_2_to_1_ellipse() {
+        let img_width = 40;
+        let settings = RenderSettings {
+            img_height: image_height(img_width, 1.0, 2.0),
+            pixel_aspect_ratio: 2.0,
+            samples_per_pixel: 1,
+            jitter: false,
+            ..test_settings(img_width, 1)
+        };
+        assert_eq!(settings.img_height, 80);
+
+        // A sphere seen head on fills half of a square view.
+        let camera = Camera::new(Vector3::new(0.0, 0.0, 4.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 90.0, settings.aspect_ratio(), 0.0, 1.0);
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 4.0 / 5.0_f64.sqrt(), Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let background = Background::Solid(Vector3::new(0.0, 0.0, 0.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let image = trace_par(&settings, &camera, &scene, &NormalsIntegrator);
+
+        let covered = |pixel: &Vector3<f64>| pixel.magnitude2() > 0.0;
+        let across = image[(40 * img_width) as usize..(41 * img_width) as usize].iter().filter(|p| covered(p)).count() as f64;
+        let down = image.iter().skip((img_width / 2) as usize).step_by(img_width as usize).filter(|p| covered(p)).count() as f64;
+        assert!((across - 20.0).abs() <= 1.0, "{} pixels across", across);
+        assert!((down / across - 2.0).abs() < 0.15, "{} down, {} across", down, across);
+    }
+
+    #[test]
+    fn matte_holds_each_objects_id_where_it_is_on_screen() {
+        let settings = test_settings(40, 20);
+        let camera = test_camera(Vector3::new(0.0, 0.0, 4.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        let left = objects.push(Sphere::new(Vector3::new(-1.0, 0.0, 0.0), 0.6, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+        let right = objects.push(Sphere::new(Vector3::new(1.0, 0.0, 0.0), 0.6, Metal::new(Vector3::new(0.5, 0.5, 0.5), 0.0)));
+
+        let ids = trace_matte_par(&settings, &camera, &objects, RayBias::default());
+        let mut distinct = ids.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct, vec![BACKGROUND_ID, left, right]);
+
+        for (i, id) in ids.iter().enumerate() {
+            let x = i as i32 % settings.img_width;
+            if *id == left { assert!(x < 20) }
+            if *id == right { assert!(x >= 20) }
+        }
+        // A corner is sky, and pixels well inside each sphere are its own.
+        assert_eq!(ids[0], BACKGROUND_ID);
+        assert_eq!(ids[10 * 40 + 10], left);
+        assert_eq!(ids[10 * 40 + 30], right);
+    }
+
+    #[test]
+    fn single_pixel_box_filter_is_the_plain_per_pixel_average() {
+        let settings = test_settings(16, 8);
+        let camera = test_camera(Vector3::new(0.0, 0.5, 3.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        generate_v1_world(&mut objects, CoordinateSystem::native());
+        let splatted = trace(&settings, &camera, &objects);
+
+        // The same samples trace_par draws, each just added to the pixel it fell in.
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let RenderSettings { img_width, img_height, samples_per_pixel, seed, .. } = settings;
+        let mut sums = vec![Vector3::new(0.0, 0.0, 0.0); (img_width * img_height) as usize];
+        for pass in 0..samples_per_pixel {
+            for row in 0..img_height {
+                raytracing::seed_rng(seed, pass as u64 * img_height as u64 + row as u64);
+                for x in 0..img_width {
+                    let s = x as f64 + settings.pixel_offset();
+                    let t = (img_height - 1 - row) as f64 + settings.pixel_offset();
+                    sums[(row * img_width + x) as usize] += sample_at(&settings, &camera, &scene, &PathIntegrator::new(), s, t);
+                }
+            }
+        }
+        let averaged: Vec<Vector3<f64>> = sums.iter().map(|sum| sum / samples_per_pixel as f64).collect();
+
+        assert_eq!(splatted, averaged);
+    }
+
+    // Mean screen column of each pixel showing the object with this id.
+    fn mean_column(ids: &[usize], img_width: i32, id: usize) -> f64 {
+        let columns: Vec<f64> = ids.iter().enumerate().filter(|(_, i)| **i == id).map(|(i, _)| (i as i32 % img_width) as f64).collect();
+        columns.iter().sum::<f64>() / columns.len() as f64
+    }
+
+    #[test]
+    fn left_handed_scenes_only_render_unmirrored_as_left_handed() {
+        // Authored left-handed, Y-up: looking down +z from behind the origin, +x is on the right.
+        let import = |handedness| {
+            let coordinates = CoordinateSystem { up: UpAxis::Y, handedness };
+            let mut objects = HittableList::new();
+            let marker = objects.push(Sphere::new(coordinates.to_world(Vector3::new(1.0, 0.0, 0.0)), 0.5, Lambertian::new(Vector3::new(0.8, 0.1, 0.1))));
+            objects.push(Sphere::new(coordinates.to_world(Vector3::new(-1.0, 0.3, 0.0)), 0.3, Lambertian::new(Vector3::new(0.1, 0.1, 0.8))));
+            let camera = test_camera(coordinates.to_world(Vector3::new(0.0, 0.0, -4.0)), coordinates.to_world(Vector3::new(0.0, 0.0, 0.0)), coordinates.up());
+            let settings = test_settings(40, 20);
+            mean_column(&trace_matte_par(&settings, &camera, &objects, RayBias::default()), settings.img_width, marker)
+        };
+
+        assert!(import(Handedness::Left) > 20.0);
+        assert!(import(Handedness::Right) < 20.0);
+    }
+
+    #[test]
+    fn preview_is_quarter_size_and_leaves_the_full_render_alone() {
+        let settings = RenderSettings {
+            img_height: image_height(64, 2.0, 1.0),
+            samples_per_pixel: 8,
+            ..test_settings(64, 1)
+        };
+        let preview = settings.preview();
+        assert_eq!((preview.img_width, preview.img_height, preview.samples_per_pixel), (16, 8, 4));
+
+        let camera = test_camera(Vector3::new(0.0, 0.5, 3.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        generate_v1_world(&mut objects, CoordinateSystem::native());
+        let direct = trace(&settings, &camera, &objects);
+        assert_eq!(trace(&preview, &camera, &objects).len(), 16 * 8);
+        assert_eq!(trace(&settings, &camera, &objects), direct);
+    }
+
+    #[test]
+    fn matte_uses_the_scene_bias() {
+        let settings = test_settings(8, 4);
+        // Both sides of the little sphere are nearer the camera than a 0.1 bias.
+        let camera = test_camera(Vector3::new(0.0, 0.0, 0.05), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        let id = objects.push(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 0.02, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))));
+
+        let center = (2 * 8 + 4) as usize;
+        assert_eq!(trace_matte_par(&settings, &camera, &objects, RayBias::default())[center], id);
+        let bias = RayBias { scatter: 0.1, ..RayBias::default() };
+        assert_eq!(trace_matte_par(&settings, &camera, &objects, bias)[center], BACKGROUND_ID);
+    }
+
+    #[test]
+    fn shards_join_into_the_full_image_byte_for_byte() {
+        // A filter wider than a pixel, so samples splat across shard edges.
+        let settings = RenderSettings { filter: Filter::Tent { radius: 1.5 }, ..test_settings(20, 11) };
+        let camera = test_camera(Vector3::new(0.0, 0.5, 3.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        generate_v1_world(&mut objects, CoordinateSystem::native());
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+
+        let render = |shard| {
+            let mut out = Vec::new();
+            render_par(&RenderSettings { shard, ..settings }, &camera, &scene, &PathIntegrator::new(), &mut out);
+            // Everything after the three header lines.
+            let header = out.iter().enumerate().filter(|(_, b)| **b == b'\n').nth(2).unwrap().0;
+            out.split_off(header + 1)
+        };
+
+        let full = render((0, 1));
+        let joined: Vec<u8> = (0..3).flat_map(|i| render((i, 3))).collect();
+        assert!(!full.is_empty());
+        assert!(full == joined);
+    }
+
+    // PPM channel values, everything after the three header lines.
+    fn ppm_values(ppm: &[u8]) -> Vec<i32> {
+        let text = std::str::from_utf8(ppm).unwrap();
+        text.lines().skip(3).flat_map(|line| line.split_whitespace().map(|value| value.parse::<i32>().unwrap())).collect()
+    }
+
+    #[test]
+    fn sixteen_bits_keep_more_of_a_gradient() {
+        let gradient: Vec<Vector3<f64>> = (0..1000).map(|i| Vector3::new(1.0, 1.0, 1.0) * (0.2 + 0.0001 * i as f64)).collect();
+        let distinct = |bit_depth| {
+            let settings = RenderSettings { bit_depth, ..test_settings(1000, 1) };
+            let mut ppm = Vec::new();
+            write_image(&settings, &gradient, &mut ppm);
+            let mut values = ppm_values(&ppm);
+            assert!(values.iter().all(|v| *v <= settings.max_value()));
+            values.sort_unstable();
+            values.dedup();
+            values.len()
+        };
+
+        let (eight, sixteen) = (distinct(8), distinct(16));
+        assert!(eight < 50, "{} values at 8 bits", eight);
+        assert!(sixteen > 10 * eight, "{} values at 16 bits, {} at 8", sixteen, eight);
+    }
+
+    #[test]
+    fn bit_depth_is_8_or_16() {
+        assert_eq!(parse_bit_depth("8"), Some(8));
+        assert_eq!(parse_bit_depth("16"), Some(16));
+        for value in ["12", "32", "0", "sixteen", ""].iter() {
+            assert_eq!(parse_bit_depth(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn pfm_keeps_radiance_unclamped_with_rows_bottom_up() {
+        // 3 by 2, top row 5.0 everywhere, bottom row 0.25 with one 2.5 pixel.
+        let mut image = vec![Vector3::new(5.0, 5.0, 5.0); 3];
+        image.extend_from_slice(&[Vector3::new(0.25, 0.25, 0.25), Vector3::new(2.5, 0.5, 0.125), Vector3::new(0.25, 0.25, 0.25)]);
+        let settings = RenderSettings { output_format: OutputFormat::Pfm, ..test_settings(3, 2) };
+        let mut pfm = Vec::new();
+        write_image(&settings, &image, &mut pfm);
+
+        // Read it back: three header lines, then little endian f32s.
+        let mut header_end = 0;
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let end = header_end + pfm[header_end..].iter().position(|b| *b == b'\n').unwrap();
+            lines.push(std::str::from_utf8(&pfm[header_end..end]).unwrap().to_string());
+            header_end = end + 1;
+        }
+        assert_eq!(lines, vec!["PF", "3 2", "-1.0"]);
+        let floats: Vec<f32> = pfm[header_end..].chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+        assert_eq!(floats.len(), 3 * 2 * 3);
+
+        // The first row stored is the bottom one.
+        assert_eq!(&floats[..9], &[0.25, 0.25, 0.25, 2.5, 0.5, 0.125, 0.25, 0.25, 0.25]);
+        assert!(floats[9..].iter().all(|f| *f == 5.0));
+    }
+
+    // Mean over pixels of each pixel's variance across renders with different seeds.
+    fn pixel_variance(renders: &[Vec<Vector3<f64>>]) -> f64 {
+        let count = renders.len() as f64;
+        let pixels = renders[0].len();
+        (0..pixels).map(|i| {
+            let mean = renders.iter().map(|render| render[i].x).sum::<f64>() / count;
+            renders.iter().map(|render| (render[i].x - mean).powi(2)).sum::<f64>() / count
+        }).sum::<f64>() / pixels as f64
+    }
+
+    #[test]
+    fn lens_samples_smooth_defocus_without_more_antialiasing() {
+        // A mirror ball catching a bright sky, well in front of the focus plane.
+        let camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 40.0, 2.0, 0.5, 10.0);
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -2.0), 0.5, Metal::new(Vector3::new(0.9, 0.9, 0.9), 0.0)));
+        let background = Background::Gradient { intensity: 4.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+
+        let variance = |lens_samples| {
+            let renders: Vec<Vec<Vector3<f64>>> = (0..20).map(|seed| {
+                let settings = RenderSettings { lens_samples, ..RenderSettings::new(16, 8, 1, 8, seed) };
+                trace_par(&settings, &camera, &scene, &PathIntegrator::new())
+            }).collect();
+            pixel_variance(&renders)
+        };
+
+        let (one, eight) = (variance(1), variance(8));
+        assert!(one > 0.0);
+        assert!(eight < 0.5 * one, "{} vs {}", eight, one);
+    }
+
+    #[test]
+    fn neighbouring_streams_are_uncorrelated_and_renders_reproducible() {
+        let stream = |stream| {
+            raytracing::seed_rng(7, stream);
+            (0..10000).map(|_| random_double()).collect::<Vec<f64>>()
+        };
+        let (a, b) = (stream(3), stream(4));
+        assert!(a != b);
+        let mean = |v: &[f64]| v.iter().sum::<f64>() / v.len() as f64;
+        let (mean_a, mean_b) = (mean(&a), mean(&b));
+        let covariance: f64 = a.iter().zip(b.iter()).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum();
+        let spread = |v: &[f64], m: f64| v.iter().map(|x| (x - m).powi(2)).sum::<f64>().sqrt();
+        let correlation = covariance / (spread(&a, mean_a) * spread(&b, mean_b));
+        // About 0.01 for independent sequences this long.
+        assert!(correlation.abs() < 0.04, "{}", correlation);
+        assert_eq!(stream(3), a);
+
+        // The same image every run, on any number of threads.
+        let settings = test_settings(20, 10);
+        let camera = test_camera(Vector3::new(0.0, 0.5, 3.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        generate_v1_world(&mut objects, CoordinateSystem::native());
+        let first = trace(&settings, &camera, &objects);
+        assert_eq!(trace(&settings, &camera, &objects), first);
+        for threads in [1, 3].iter() {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(*threads).build().unwrap();
+            assert_eq!(pool.install(|| trace(&settings, &camera, &objects)), first);
+        }
+    }
+
+    #[test]
+    fn unjittered_single_samples_give_hard_reproducible_edges() {
+        // A grey mirror under a white sky only ever shows two colors.
+        let camera = test_camera(Vector3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 0.5, Metal::new(Vector3::new(0.5, 0.5, 0.5), 0.0)));
+        let background = Background::Solid(Vector3::new(1.0, 1.0, 1.0));
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let distinct = |settings: &RenderSettings| {
+            let image = trace_par(settings, &camera, &scene, &PathIntegrator::new());
+            let mut values: Vec<f64> = image.iter().map(|c| c.x).collect();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            values.dedup();
+            (image, values.len())
+        };
+
+        let sharp = RenderSettings { jitter: false, ..RenderSettings::new(40, 20, 1, 8, 1) };
+        let (image, values) = distinct(&sharp);
+        assert_eq!(values, 2);
+        assert_eq!(distinct(&RenderSettings { seed: 2, ..sharp }).0, image);
+        // Jittered and averaged, the edge pixels land in between.
+        assert!(distinct(&RenderSettings::new(40, 20, 8, 8, 1)).1 > 2);
+    }
+
+    #[test]
+    fn lower_white_points_reach_white_sooner_and_keep_midtones() {
+        let grey = |c: f64| Vector3::new(c, c, c);
+        let (low, high) = (ToneMap::Reinhard { white: 2.0 }, ToneMap::Reinhard { white: 8.0 });
+        assert_eq!(low.apply(grey(2.0)), grey(1.0));
+        assert!(high.apply(grey(2.0)).x < 0.8);
+        assert!((high.apply(grey(8.0)).x - 1.0).abs() < 1e-12);
+
+        let plain = ToneMap::Reinhard { white: f64::INFINITY };
+        for c in [0.05, 0.18, 0.3].iter() {
+            assert!((low.apply(grey(*c)).x - plain.apply(grey(*c)).x).abs() < 0.02, "{}", c);
+            assert_eq!(plain.apply(grey(*c)).x, c / (1.0 + c));
+        }
+    }
+}
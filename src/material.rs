@@ -1,10 +1,13 @@
 use cgmath::*;
+use crate::distribution::*;
 use crate::hittable::HitRecord;
-use crate::ray::Ray;
+use crate::ray::{MediumStack, Ray};
+use crate::texture::*;
 use rand::*;
+use std::sync::Arc;
 
 fn clamp(x: f64, min: f64, max: f64) -> f64 {
-    return if x < min { min } else if x > max { max } else { x }
+    if x < min { min } else if x > max { max } else { x }
 }
 
 fn reflect(v: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
@@ -13,7 +16,7 @@ fn reflect(v: Vector3<f64>, n: Vector3<f64>) -> Vector3<f64> {
 
 fn near_zero(vec: Vector3<f64>) -> bool {
     let s = 1e-8;
-    return vec.x < s && vec.y < s && vec.z < s;
+    vec.x.abs() < s && vec.y.abs() < s && vec.z.abs() < s
 }
 
 fn refract(uv: Vector3<f64>, n: Vector3<f64>, etai_over_etat: f64) -> Vector3<f64> {
@@ -26,28 +29,120 @@ fn refract(uv: Vector3<f64>, n: Vector3<f64>, etai_over_etat: f64) -> Vector3<f6
 
 pub trait Material: Send + Sync{
     fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f64>)>;
+
+    // Whether scattered rays are mirror-like reflections/refractions rather than diffuse bounces.
+    fn is_specular(&self) -> bool {
+        false
+    }
+
+    // Albedo at the hit for perfectly diffuse materials, None for everything else.
+    // Lets code that does its own direction sampling evaluate the Lambertian BRDF.
+    fn diffuse_albedo(&self, _hit: &HitRecord) -> Option<Vector3<f64>> {
+        None
+    }
+}
+
+// Lets many objects share one material.
+impl<M: Material + ?Sized> Material for Arc<M> {
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f64>)> {
+        (**self).scatter(ray, hit)
+    }
+
+    fn is_specular(&self) -> bool {
+        (**self).is_specular()
+    }
+
+    fn diffuse_albedo(&self, hit: &HitRecord) -> Option<Vector3<f64>> {
+        (**self).diffuse_albedo(hit)
+    }
 }
 
 pub struct Metal {
     albedo: Vector3<f64>,
     fuzz: f64,
+    // Overrides fuzz per hit when set, read from the texture's first channel.
+    fuzz_map: Option<Box<dyn Texture>>,
 }
 
 impl Metal {
+    // Fuzz is clamped to [0.0, 1.0]: 0.0 is a perfect mirror, 1.0 the blurriest reflection.
+    // Negative or NaN fuzz would perturb reflections inward, so both become 0.0.
     pub fn new(albedo: Vector3<f64>, fuzz: f64) -> Self {
+        if fuzz.is_nan() {
+            eprintln!("Metal fuzz is NaN, using 0.0");
+        }
         Metal {
-            albedo: albedo,
-            fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
+            albedo: sanitize_color("Metal albedo", albedo),
+            fuzz: sanitize_fuzz(fuzz),
+            fuzz_map: None,
+        }
+    }
+
+    // Spatially varying roughness, e.g. smudges on a polished surface.
+    // The texture's first channel is the fuzz, clamped the same way as in new.
+    pub fn with_fuzz_texture(albedo: Vector3<f64>, fuzz: impl Texture + 'static) -> Self {
+        Metal {
+            albedo: sanitize_color("Metal albedo", albedo),
+            fuzz: 0.0,
+            fuzz_map: Some(Box::new(fuzz)),
+        }
+    }
+
+    fn fuzz_at(&self, hit: &HitRecord) -> f64 {
+        match &self.fuzz_map {
+            Some(texture) => sanitize_fuzz(texture.value(hit.u, hit.v, hit.point).x),
+            None => self.fuzz,
         }
     }
 }
 
+fn sanitize_fuzz(fuzz: f64) -> f64 {
+    if fuzz.is_nan() { 0.0 } else { clamp(fuzz, 0.0, 1.0) }
+}
+
+// A NaN or infinite parameter, say from a bad scene file, would otherwise spread through
+// every path that touches the material. Constructors swap them for a safe value and say so.
+fn sanitize_color(what: &str, color: Vector3<f64>) -> Vector3<f64> {
+    if color.x.is_finite() && color.y.is_finite() && color.z.is_finite() {
+        return color
+    }
+    eprintln!("{} {:?} isn't finite, using 0.0 for the bad channels", what, color);
+    color.map(|c| if c.is_finite() { c } else { 0.0 })
+}
+
+// Non-finite or non-positive ir becomes 1.0, which doesn't bend light at all.
+fn sanitize_ir(ir: f64) -> f64 {
+    if ir.is_finite() && ir > 0.0 {
+        return ir
+    }
+    eprintln!("Dielectric ir {} isn't a positive number, using 1.0", ir);
+    1.0
+}
+
+// sanitize_ir for values read per hit, so without the warning.
+fn sanitize_ir_texel(ir: f64) -> f64 {
+    if ir.is_finite() && ir > 0.0 { ir } else { 1.0 }
+}
+
+fn sanitize_roughness(roughness: f64) -> f64 {
+    if roughness.is_nan() {
+        eprintln!("Dielectric roughness is NaN, using 0.0");
+        return 0.0
+    }
+    clamp(roughness, 0.0, 1.0)
+}
+
 impl Material for Metal {
+    fn is_specular(&self) -> bool {
+        true
+    }
+
     fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f64>)> {
         let mut reflected = reflect(ray.dir.normalize(), hit.normal);
-        if self.fuzz > 0.0 { reflected += self.fuzz * crate::random_in_unit_sphere() }
+        let fuzz = self.fuzz_at(hit);
+        if fuzz > 0.0 { reflected += fuzz * crate::random_in_unit_sphere() }
         if reflected.dot(hit.normal) > 0.0 {
-            let scattered = Ray::new(hit.point, reflected);
+            let scattered = Ray::with_media(hit.point, reflected, ray.media);
             Some((scattered, self.albedo))
         }
         else {
@@ -56,63 +151,218 @@ impl Material for Metal {
     }
 }
 
-pub struct Lambertian {
-    albedo: Vector3<f64>,
+pub struct Lambertian<D: ScatterDistribution = UnitVector> {
+    albedo: Box<dyn Texture>,
+    distribution: D,
+    normalized: bool,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vector3<f64>) -> Self {
+        Lambertian::with_distribution(albedo, UnitVector)
+    }
+
+    pub fn textured(albedo: impl Texture + 'static) -> Self {
+        Lambertian::textured_with_distribution(albedo, UnitVector)
+    }
+}
+
+impl<D: ScatterDistribution> Lambertian<D> {
+    // Non-finite albedo channels become 0.0, with a warning.
+    pub fn with_distribution(albedo: Vector3<f64>, distribution: D) -> Self {
+        Lambertian::textured_with_distribution(SolidColor::new(sanitize_color("Lambertian albedo", albedo)), distribution)
+    }
+
+    pub fn textured_with_distribution(albedo: impl Texture + 'static, distribution: D) -> Self {
         Lambertian {
-            albedo: albedo,
+            albedo: Box::new(albedo),
+            distribution,
+            normalized: false,
         }
     }
+
+    // By default scatter returns the albedo as the attenuation, the book's convention.
+    // That's only right for the cosine distribution (UnitVector). Normalized, the attenuation
+    // is the full BRDF * cos / pdf with the BRDF's 1/pi, which is energy correct for any
+    // distribution and identical to the default for UnitVector.
+    pub fn normalized(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
 }
 
-impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f64>)> {
-        let mut scatter_direction = hit.normal + crate::random_unit_vector();
+impl<D: ScatterDistribution> Material for Lambertian<D> {
+    fn diffuse_albedo(&self, hit: &HitRecord) -> Option<Vector3<f64>> {
+        Some(self.albedo.value(hit.u, hit.v, hit.point))
+    }
+
+    fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f64>)> {
+        let mut scatter_direction = crate::with_rng(|rng| self.distribution.sample(rng, hit.normal));
 
         if near_zero(scatter_direction) {
             scatter_direction = hit.normal;
         }
 
-        Some((Ray::new(hit.point, scatter_direction), self.albedo))
+        let albedo = self.albedo.value(hit.u, hit.v, hit.point);
+        let attenuation = if self.normalized {
+            let pdf = self.distribution.pdf(hit.normal, scatter_direction);
+            if pdf <= 0.0 { return None }
+            let cos_theta = hit.normal.dot(scatter_direction.normalize()).max(0.0);
+            albedo * (cos_theta / crate::PI / pdf)
+        } else {
+            albedo
+        };
+
+        Some((Ray::with_media(hit.point, scatter_direction, ray.media), attenuation))
     }
 }
 
 pub struct Dielectric {
     ir: f64,
+    roughness: f64,
+    // Overrides ir per hit when set, read from the texture's first channel.
+    ir_map: Option<Box<dyn Texture>>,
+    fresnel: Fresnel,
+}
+
+// How a dielectric splits light between reflection and refraction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fresnel {
+    // Schlick's approximation. Cheap, and close to exact except near grazing angles.
+    Schlick,
+    // The full Fresnel equations, s and p polarizations averaged.
+    Exact,
+}
+
+impl Fresnel {
+    // Fraction of light reflected arriving at cos_theta to the normal, moving from
+    // a medium into one with refraction_ratio times less ir.
+    pub fn reflectance(&self, cos_theta: f64, refraction_ratio: f64) -> f64 {
+        match self {
+            Fresnel::Schlick => reflectance(cos_theta, refraction_ratio),
+            Fresnel::Exact => {
+                let sin_t = refraction_ratio * (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                if sin_t >= 1.0 {
+                    return 1.0
+                }
+                let cos_t = (1.0 - sin_t * sin_t).sqrt();
+                let r_s = (refraction_ratio * cos_theta - cos_t) / (refraction_ratio * cos_theta + cos_t);
+                let r_p = (cos_theta - refraction_ratio * cos_t) / (cos_theta + refraction_ratio * cos_t);
+                0.5 * (r_s * r_s + r_p * r_p)
+            }
+        }
+    }
 }
 
 impl Dielectric {
     pub fn new(ir: f64) -> Self {
+        Dielectric::with_roughness(ir, 0.0)
+    }
+
+    // Frosted glass. Roughness is clamped to [0.0, 1.0], 0.0 is a perfectly smooth surface.
+    pub fn with_roughness(ir: f64, roughness: f64) -> Self {
         Dielectric {
-            ir: ir,
+            ir: sanitize_ir(ir),
+            roughness: sanitize_roughness(roughness),
+            ir_map: None,
+            fresnel: Fresnel::Schlick,
+        }
+    }
+
+    // Spatially varying index of refraction, e.g. fingerprints or density changes in glass.
+    // The texture's first channel is the ir at each hit.
+    pub fn with_ir_texture(ir: impl Texture + 'static, roughness: f64) -> Self {
+        Dielectric {
+            ir: 1.0,
+            roughness: sanitize_roughness(roughness),
+            ir_map: Some(Box::new(ir)),
+            fresnel: Fresnel::Schlick,
+        }
+    }
+
+    // Schlick by default, Fresnel::Exact for accuracy at grazing angles and high ir.
+    pub fn with_fresnel(mut self, fresnel: Fresnel) -> Self {
+        self.fresnel = fresnel;
+        self
+    }
+
+    fn ir_at(&self, hit: &HitRecord) -> f64 {
+        match &self.ir_map {
+            Some(texture) => sanitize_ir_texel(texture.value(hit.u, hit.v, hit.point).x),
+            None => self.ir,
+        }
+    }
+
+    // Reflect or refract about a GGX-sampled microfacet normal instead of the surface normal.
+    fn scatter_rough(&self, ray: &Ray, hit: &HitRecord, refraction_ratio: f64, refracted_media: MediumStack) -> Option<(Ray, Vector3<f64>)> {
+        let alpha = self.roughness * self.roughness;
+        let unit_direction = ray.dir.normalize();
+        let to_viewer = -unit_direction;
+
+        let mut microfacet = sample_ggx(hit.normal, alpha);
+        if to_viewer.dot(microfacet) <= 0.0 {
+            microfacet = hit.normal;
         }
+
+        let cos_theta = to_viewer.dot(microfacet).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+
+        let scattered = if cannot_refract || self.fresnel.reflectance(cos_theta, refraction_ratio) > crate::random_double() {
+            let direction = reflect(unit_direction, microfacet);
+            if direction.dot(hit.normal) <= 0.0 { return None }
+            Ray::with_media(hit.point, direction, ray.media)
+        }
+        else {
+            let direction = refract(unit_direction, microfacet, refraction_ratio);
+            if direction.dot(hit.normal) >= 0.0 { return None }
+            Ray::with_media(hit.point, direction, refracted_media)
+        };
+
+        // Sampling the half vector by D(m)cos(m) leaves the Smith shadowing-masking
+        // term and the change of measure as the sample weight.
+        let out_direction = scattered.dir.normalize();
+        let weight = cos_theta * smith_g1(to_viewer, hit.normal, alpha) * smith_g1(out_direction, hit.normal, alpha)
+            / (to_viewer.dot(hit.normal).abs() * microfacet.dot(hit.normal).abs()).max(1e-8);
+
+        Some((scattered, Vector3::new(weight, weight, weight)))
     }
 }
 
 impl Material for Dielectric {
+    fn is_specular(&self) -> bool {
+        true
+    }
+
     fn scatter(&self, ray: &Ray, hit: &HitRecord) -> Option<(Ray, Vector3<f64>)> {
         let attenuation = Vector3::<f64>::new(1.0, 1.0, 1.0);
+        let ir = self.ir_at(hit);
+
+        // Entering pushes our ir onto the ray's medium stack, exiting pops it,
+        // so a bubble inside water refracts water-to-air rather than glass-to-vacuum.
+        let (refraction_ratio, refracted_media) = if hit.front_face {
+            (ray.media.current() / ir, ray.media.push(ir))
+        } else {
+            (ir / ray.media.outer(), ray.media.pop())
+        };
+        if self.roughness > 0.0 {
+            return self.scatter_rough(ray, hit, refraction_ratio, refracted_media);
+        }
 
-        let refraction_ratio = if hit.front_face {1.0/self.ir} else {self.ir};
         let unit_direction = ray.dir.normalize();
 
-        let cos_theta = clamp((-unit_direction).dot(hit.normal), 1.0, f64::MAX);
+        let cos_theta = clamp((-unit_direction).dot(hit.normal), 0.0, 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction: Vector3<f64>;
-
-        if cannot_refract || reflectance(cos_theta, refraction_ratio) > random::<f64>() {
-            direction = reflect(unit_direction, hit.normal);
+        let scattered = if cannot_refract || self.fresnel.reflectance(cos_theta, refraction_ratio) > crate::random_double() {
+            Ray::with_media(hit.point, reflect(unit_direction, hit.normal), ray.media)
         }
         else {
-            direction = refract(unit_direction, hit.normal, refraction_ratio);
-        }
+            Ray::with_media(hit.point, refract(unit_direction, hit.normal, refraction_ratio), refracted_media)
+        };
 
-        Some((Ray::new(hit.point, direction), attenuation))
+        Some((scattered, attenuation))
     }
 }
 
@@ -121,4 +371,234 @@ fn reflectance(cosine: f64, refraction: f64) -> f64 {
     let mut r0 = (1.0 - refraction) / (1.0 + refraction);
     r0 = r0*r0;
     r0 + (1.0 - r0)*(1.0 - cosine).powf(5.0)
-}
\ No newline at end of file
+}
+
+// Sample a GGX microfacet normal around n, distributed by D(m)cos(m).
+fn sample_ggx(n: Vector3<f64>, alpha: f64) -> Vector3<f64> {
+    let (r1, r2): (f64, f64) = crate::with_rng(|rng| (rng.gen(), rng.gen()));
+
+    let theta = (alpha * (r1 / (1.0 - r1)).sqrt()).atan();
+    let phi = 2.0 * crate::PI * r2;
+
+    // Any two vectors perpendicular to n will do for the tangent frame.
+    let helper = if n.x.abs() > 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(1.0, 0.0, 0.0) };
+    let tangent = n.cross(helper).normalize();
+    let bitangent = n.cross(tangent);
+
+    (theta.sin() * phi.cos() * tangent + theta.sin() * phi.sin() * bitangent + theta.cos() * n).normalize()
+}
+
+// Smith masking term for GGX along direction v.
+fn smith_g1(v: Vector3<f64>, n: Vector3<f64>, alpha: f64) -> f64 {
+    let cos = v.dot(n).abs().min(1.0);
+    if cos <= 0.0 { return 0.0 }
+    let tan2 = (1.0 - cos * cos) / (cos * cos);
+    2.0 / (1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A hit on a horizontal surface at the origin, normal straight up.
+    fn hit_on(material: &dyn Material, front_face: bool) -> HitRecord<'_> {
+        HitRecord {
+            point: Vector3::new(0.0, 0.0, 0.0),
+            normal: Vector3::new(0.0, 1.0, 0.0),
+            t: 1.0,
+            u: 0.5,
+            v: 0.5,
+            front_face,
+            material,
+            object_id: 0,
+        }
+    }
+
+    // Scatters until the ray refracts rather than reflects, returning sin of the refracted angle.
+    fn refracted_sine(material: &dyn Material, ray: &Ray, hit: &HitRecord) -> (f64, MediumStack) {
+        crate::seed_rng(1, 0);
+        loop {
+            let (scattered, _) = material.scatter(ray, hit).expect("smooth glass always scatters");
+            let direction = scattered.dir.normalize();
+            if direction.dot(hit.normal) < 0.0 {
+                return (direction.cross(hit.normal).magnitude(), scattered.media)
+            }
+        }
+    }
+
+    // ir 1.3 where x < 0, 1.8 elsewhere.
+    struct TwoRegions;
+
+    impl Texture for TwoRegions {
+        fn value(&self, _: f64, _: f64, p: Vector3<f64>) -> Vector3<f64> {
+            let ir = if p.x < 0.0 { 1.3 } else { 1.8 };
+            Vector3::new(ir, ir, ir)
+        }
+    }
+
+    #[test]
+    fn ir_texture_refracts_differently_in_each_region() {
+        let glass = Dielectric::with_ir_texture(TwoRegions, 0.0);
+        let sin_incident = std::f64::consts::FRAC_1_SQRT_2;
+        for (x, ir) in [(-1.0, 1.3), (1.0, 1.8)].iter() {
+            let mut hit = hit_on(&glass, true);
+            hit.point = Vector3::new(*x, 0.0, 0.0);
+            let ray = Ray::new(hit.point + Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+            let (sine, _) = refracted_sine(&glass, &ray, &hit);
+            assert!((sine - sin_incident / ir).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn bad_ir_texels_dont_bend_light() {
+        for ir in [0.0, -1.5, f64::NAN, f64::INFINITY].iter() {
+            let glass = Dielectric::with_ir_texture(SolidColor::new(Vector3::new(*ir, *ir, *ir)), 0.0);
+            let hit = hit_on(&glass, true);
+            let ray = Ray::new(Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+            let (sine, media) = refracted_sine(&glass, &ray, &hit);
+            assert!((sine - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+            assert_eq!(media.current(), 1.0);
+        }
+    }
+
+    #[test]
+    fn glass_inside_water_refracts_by_the_ratio_of_their_irs() {
+        let glass = Dielectric::new(1.5);
+        let hit = hit_on(&glass, true);
+        let incoming = Vector3::new(1.0, -1.0, 0.0);
+        let sin_incident = std::f64::consts::FRAC_1_SQRT_2;
+
+        let in_water = Ray::with_media(Vector3::new(-1.0, 1.0, 0.0), incoming, MediumStack::new().push(1.33));
+        let (sine, media) = refracted_sine(&glass, &in_water, &hit);
+        assert!((sine - sin_incident * 1.33 / 1.5).abs() < 1e-9);
+        assert_eq!((media.current(), media.outer()), (1.5, 1.33));
+
+        // The same ray from vacuum bends more.
+        let in_vacuum = Ray::new(Vector3::new(-1.0, 1.0, 0.0), incoming);
+        let (sine, _) = refracted_sine(&glass, &in_vacuum, &hit);
+        assert!((sine - sin_incident / 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn leaving_glass_inside_water_refracts_back_into_water() {
+        let glass = Dielectric::new(1.5);
+        // A back face hit, the normal already flipped to face the ray as set_face_normal does.
+        let hit = hit_on(&glass, false);
+        let in_glass = Ray::with_media(Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -2.0, 0.0), MediumStack::new().push(1.33).push(1.5));
+        let sin_incident = 1.0 / 5.0_f64.sqrt();
+
+        let (sine, media) = refracted_sine(&glass, &in_glass, &hit);
+        assert!((sine - sin_incident * 1.5 / 1.33).abs() < 1e-9);
+        assert_eq!(media.current(), 1.33);
+    }
+
+    // Mean angle between the directions rough glass transmits and the ideal refracted one.
+    fn mean_transmission_spread(glass: &Dielectric) -> f64 {
+        let hit = hit_on(glass, true);
+        let ray = Ray::new(Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+        let ideal = refract(ray.dir.normalize(), hit.normal, 1.0 / 1.5);
+        crate::seed_rng(2, 0);
+        let angles: Vec<f64> = (0..4000).filter_map(|_| glass.scatter(&ray, &hit))
+            .map(|(scattered, _)| scattered.dir.normalize())
+            .filter(|direction| direction.dot(hit.normal) < 0.0)
+            .map(|direction| direction.dot(ideal).min(1.0).acos())
+            .collect();
+        assert!(angles.len() > 1000);
+        angles.iter().sum::<f64>() / angles.len() as f64
+    }
+
+    #[test]
+    fn rough_transmission_tightens_around_refraction_as_roughness_drops() {
+        let spreads: Vec<f64> = [0.5, 0.1, 0.01].iter().map(|roughness| mean_transmission_spread(&Dielectric::with_roughness(1.5, *roughness))).collect();
+        assert!(spreads[0] > spreads[1] && spreads[1] > spreads[2], "{:?}", spreads);
+        assert!(spreads[2] < 1e-3, "{:?}", spreads);
+    }
+
+    #[test]
+    fn zero_roughness_is_smooth_glass() {
+        let (rough, smooth) = (Dielectric::with_roughness(1.5, 0.0), Dielectric::new(1.5));
+        let ray = Ray::new(Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+        for i in 0..200 {
+            crate::seed_rng(4, i);
+            let (a, a_atten) = rough.scatter(&ray, &hit_on(&rough, true)).unwrap();
+            crate::seed_rng(4, i);
+            let (b, b_atten) = smooth.scatter(&ray, &hit_on(&smooth, true)).unwrap();
+            assert_eq!((a.origin, a.dir, a_atten), (b.origin, b.dir, b_atten));
+        }
+    }
+
+    // What a diffuse surface facing straight up reflects of light that falls off as the cosine
+    // from the zenith, the attenuation times that light averaged over many scatters.
+    fn reflected_zenith_light(material: &dyn Material) -> f64 {
+        let hit = hit_on(material, true);
+        let ray = Ray::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, -1.0, 0.0));
+        crate::seed_rng(6, 0);
+        let samples = 50_000;
+        let sum: f64 = (0..samples).filter_map(|_| material.scatter(&ray, &hit))
+            .map(|(scattered, atten)| atten.x * scattered.dir.normalize().y.max(0.0))
+            .sum();
+        sum / samples as f64
+    }
+
+    #[test]
+    fn normalized_lambertians_agree_where_the_book_convention_doesnt() {
+        // An energy correct surface reflects 2/3 of this light.
+        let correct = 2.0 / 3.0;
+        let albedo = Vector3::new(1.0, 1.0, 1.0);
+        let book_hemisphere = reflected_zenith_light(&Lambertian::with_distribution(albedo, Hemisphere));
+        let book_in_sphere = reflected_zenith_light(&Lambertian::with_distribution(albedo, InUnitSphere));
+        // Uniform sampling undercounts the bright zenith, InUnitSphere's bias towards it overcounts.
+        assert!((book_hemisphere - 0.5).abs() < 0.01, "{}", book_hemisphere);
+        assert!((book_in_sphere - 0.8).abs() < 0.01, "{}", book_in_sphere);
+
+        for normalized in [
+            reflected_zenith_light(&Lambertian::with_distribution(albedo, Hemisphere).normalized(true)),
+            reflected_zenith_light(&Lambertian::with_distribution(albedo, InUnitSphere).normalized(true)),
+            reflected_zenith_light(&Lambertian::new(albedo).normalized(true)),
+            reflected_zenith_light(&Lambertian::new(albedo)),
+        ].iter() {
+            assert!((normalized - correct).abs() < 0.01, "{}", normalized);
+        }
+    }
+
+    #[test]
+    fn negative_or_nan_fuzz_is_a_perfect_mirror() {
+        let albedo = Vector3::new(0.9, 0.8, 0.7);
+        let ray = Ray::new(Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+        for fuzz in [-0.5, f64::NAN].iter() {
+            let metal = Metal::new(albedo, *fuzz);
+            assert_eq!(metal.fuzz, 0.0);
+            let hit = hit_on(&metal, true);
+            for _ in 0..100 {
+                let (scattered, atten) = metal.scatter(&ray, &hit).expect("a mirror always reflects");
+                assert_eq!(scattered.dir, reflect(ray.dir.normalize(), hit.normal));
+                assert_eq!(atten, albedo);
+            }
+        }
+    }
+
+    #[test]
+    fn nan_parameters_get_their_documented_defaults() {
+        let lambertian = Lambertian::new(Vector3::new(0.5, f64::NAN, 0.25));
+        assert_eq!(lambertian.diffuse_albedo(&hit_on(&lambertian, true)), Some(Vector3::new(0.5, 0.0, 0.25)));
+
+        // ir 1.0 doesn't bend light.
+        let glass = Dielectric::new(f64::NAN);
+        assert_eq!(glass.ir, 1.0);
+        let ray = Ray::new(Vector3::new(-1.0, 1.0, 0.0), Vector3::new(1.0, -1.0, 0.0));
+        let (sine, _) = refracted_sine(&glass, &ray, &hit_on(&glass, true));
+        assert!((sine - std::f64::consts::FRAC_1_SQRT_2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn exact_fresnel_matches_schlick_head_on_but_not_near_grazing() {
+        // Air into diamond.
+        let ratio = 1.0 / 2.42;
+        let (exact, schlick) = (Fresnel::Exact.reflectance(1.0, ratio), Fresnel::Schlick.reflectance(1.0, ratio));
+        assert!((exact - schlick).abs() < 1e-12, "{} vs {}", exact, schlick);
+
+        let cos_80 = 80.0_f64.to_radians().cos();
+        let (exact, schlick) = (Fresnel::Exact.reflectance(cos_80, ratio), Fresnel::Schlick.reflectance(cos_80, ratio));
+        assert!((exact - schlick).abs() > 0.03, "{} vs {}", exact, schlick);
+    }
+}
@@ -1,19 +1,131 @@
 use cgmath::*;
 
+// How many dielectrics a ray can be nested inside before we stop tracking them.
+const MAX_NESTED_MEDIA: usize = 8;
+
+// Indices of refraction of the media a ray is travelling through, innermost last.
+// An empty stack means vacuum (ir 1.0). Fixed size so building a ray doesn't allocate.
+// Media entered past MAX_NESTED_MEDIA are only counted, so their exits pop them instead of
+// a tracked medium and everything outside stays in step. Inside them the ray is taken to
+// still be in the innermost tracked medium.
+#[derive(Clone, Copy, Debug)]
+pub struct MediumStack {
+    iors: [f64; MAX_NESTED_MEDIA],
+    len: usize,
+    untracked: usize,
+}
+
+impl MediumStack {
+    pub fn new() -> Self {
+        Self {
+            iors: [1.0; MAX_NESTED_MEDIA],
+            len: 0,
+            untracked: 0,
+        }
+    }
+
+    // Index of refraction of the medium the ray is currently in.
+    pub fn current(&self) -> f64 {
+        if self.len == 0 { 1.0 } else { self.iors[self.len - 1] }
+    }
+
+    // Index of refraction of the medium surrounding the current one,
+    // i.e. what the ray will be in after it exits.
+    pub fn outer(&self) -> f64 {
+        if self.untracked > 0 {
+            self.current()
+        } else if self.len < 2 {
+            1.0
+        } else {
+            self.iors[self.len - 2]
+        }
+    }
+
+    pub fn push(&self, ir: f64) -> Self {
+        let mut media = *self;
+        if media.len < MAX_NESTED_MEDIA {
+            media.iors[media.len] = ir;
+            media.len += 1;
+        } else {
+            media.untracked += 1;
+        }
+        media
+    }
+
+    pub fn pop(&self) -> Self {
+        let mut media = *self;
+        if media.untracked > 0 {
+            media.untracked -= 1;
+        } else if media.len > 0 {
+            media.len -= 1;
+        }
+        media
+    }
+}
+
+impl Default for MediumStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Ray {
     pub origin: Vector3<f64>,
     pub dir: Vector3<f64>,
+    pub media: MediumStack,
+    // Farthest t a hit along this ray counts, the camera's far clip for primary rays.
+    pub t_max: f64,
+    // Id of a HittableList object this ray passes straight through,
+    // 0 (BACKGROUND_ID) for none. Lets camera rays see out of an object around the camera.
+    pub skip_object: usize,
 }
 
 impl Ray {
     pub fn new(orig: Vector3<f64>, direction: Vector3<f64>) -> Ray {
+        Self::with_media(orig, direction, MediumStack::new())
+    }
+
+    pub fn with_media(orig: Vector3<f64>, direction: Vector3<f64>, media: MediumStack) -> Ray {
         Self {
             origin: orig,
             dir: direction,
+            media,
+            t_max: f64::INFINITY,
+            skip_object: crate::hittable::BACKGROUND_ID,
         }
     }
 
-    pub fn at(self: &Self, t: f64) -> Vector3<f64> {
+    // A zero, infinite or NaN direction can't be normalized, and would spread NaNs
+    // through everything that touches it.
+    pub fn is_degenerate(&self) -> bool {
+        let length_squared = self.dir.magnitude2();
+        !(length_squared > 1e-16 && length_squared.is_finite())
+    }
+
+    pub fn at(&self, t: f64) -> Vector3<f64> {
         self.origin + t*self.dir
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exits_past_the_nesting_limit_stay_in_step() {
+        let iors: Vec<f64> = (1..=MAX_NESTED_MEDIA + 1).map(|i| 1.0 + 0.1 * i as f64).collect();
+        let mut media = MediumStack::new();
+        for ir in iors.iter() {
+            media = media.push(*ir);
+        }
+
+        // Leaving the untracked innermost medium goes back into the 8th.
+        assert_eq!(media.outer(), iors[MAX_NESTED_MEDIA - 1]);
+        for exited in 1..=iors.len() {
+            media = media.pop();
+            let expected = if exited == iors.len() { 1.0 } else { iors[iors.len() - 1 - exited] };
+            assert_eq!(media.current(), expected, "after {} exits", exited);
+        }
+        assert_eq!(media.pop().current(), 1.0);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::camera::Camera;
+use crate::integrator::Scene;
+use crate::ray::Ray;
+use cgmath::*;
+
+// Deliberately simple, slow ground truth to check the real renderer against.
+// Serial, no acceleration, no importance sampling: diffuse bounces are sampled uniformly
+// over the hemisphere and weighted by the Lambertian BRDF, everything else uses the
+// material's own scatter. Returns averaged colors, top row first.
+// Every random number comes from the crate's RNG, restarted per row on a stream of seed,
+// so the same seed always gives the same image.
+pub fn reference_render(camera: &Camera, scene: &Scene, width: i32, height: i32, samples_per_pixel: i32, max_depth: i32, seed: u64) -> Vec<Vector3<f64>> {
+    let mut image = Vec::with_capacity((width * height) as usize);
+
+    for y in (0..height).rev() {
+        crate::seed_rng(seed, (height - 1 - y) as u64);
+        for x in 0..width {
+            let mut sum = Vector3::new(0.0, 0.0, 0.0);
+            for _ in 0..samples_per_pixel {
+                let u = (x as f64 + crate::random_double()) / (width - 1) as f64;
+                let v = (y as f64 + crate::random_double()) / (height - 1) as f64;
+                sum += reference_radiance(&camera.get_ray(u, v), scene, max_depth, true);
+            }
+            image.push(sum / samples_per_pixel.max(1) as f64);
+        }
+    }
+    image
+}
+
+fn reference_radiance(ray: &Ray, scene: &Scene, depth: i32, primary: bool) -> Vector3<f64> {
+    if depth <= 0 {
+        return Vector3::new(0.0, 0.0, 0.0);
+    }
+
+    let hit = match scene.objects.hit(ray, scene.bias.scatter, ray.t_max) {
+        Some(hit) => hit,
+        None => return scene.miss(ray, primary),
+    };
+
+    if let Some(albedo) = hit.material.diffuse_albedo(&hit) {
+        // Uniform hemisphere pdf is 1/(2 pi) and the BRDF is albedo/pi,
+        // so each sample is weighted by 2 * albedo * cos(theta).
+        let mut direction = crate::random_unit_vector();
+        if direction.dot(hit.normal) < 0.0 {
+            direction = -direction;
+        }
+        let weight = 2.0 * direction.dot(hit.normal) * albedo;
+        let incoming = reference_radiance(&Ray::with_media(hit.point, direction, ray.media), scene, depth - 1, false);
+        return weight.zip(incoming, |l, r| l * r);
+    }
+
+    match hit.material.scatter(ray, &hit) {
+        Some((scattered, atten)) => atten.zip(reference_radiance(&scattered, scene, depth - 1, false), |l, r| l * r),
+        None => Vector3::new(0.0, 0.0, 0.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::Background;
+    use crate::hittable_list::HittableList;
+    use crate::integrator::{PathIntegrator, RayBias};
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::render::{trace_par, RenderSettings};
+
+    const WIDTH: i32 = 8;
+    const HEIGHT: i32 = 4;
+    const SAMPLES: i32 = 2048;
+    const DEPTH: i32 = 8;
+
+    fn diffuse_scene() -> HittableList {
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, Lambertian::new(Vector3::new(0.6, 0.6, 0.6))));
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.7, 0.3, 0.2))));
+        objects
+    }
+
+    fn camera() -> Camera {
+        Camera::new(Vector3::new(0.0, 0.3, 1.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 60.0, 2.0, 0.0, 2.0)
+    }
+
+    #[test]
+    fn same_seed_same_reference() {
+        let objects = diffuse_scene();
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let first = reference_render(&camera(), &scene, WIDTH, HEIGHT, 4, DEPTH, 11);
+        assert_eq!(first, reference_render(&camera(), &scene, WIDTH, HEIGHT, 4, DEPTH, 11));
+        assert_ne!(first, reference_render(&camera(), &scene, WIDTH, HEIGHT, 4, DEPTH, 12));
+    }
+
+    #[test]
+    fn path_integrator_matches_the_reference() {
+        let objects = diffuse_scene();
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let reference = reference_render(&camera(), &scene, WIDTH, HEIGHT, SAMPLES, DEPTH, 1);
+
+        let image = trace_par(&RenderSettings::new(WIDTH, HEIGHT, SAMPLES, DEPTH, 2), &camera(), &scene, &PathIntegrator::new());
+
+        let mse = image.iter().zip(reference.iter()).map(|(a, b)| (a - b).magnitude2() / 3.0).sum::<f64>() / image.len() as f64;
+        // Noise alone is about 3e-5 here, cutting the paths to two bounces is nearer 2e-4.
+        assert!(mse < 1e-4, "mse {}", mse);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::camera::{image_height, Camera};
+use crate::integrator::{Integrator, Scene};
+use cgmath::*;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Set to stop renders early, the binary sets it on Ctrl-C. Renders check it before each
+// pass and keep the passes already taken, so the image is evenly sampled, just noisier.
+pub static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+// File format the binary writes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    // Gamma corrected, clamped, 8 or 16 bit text PPM.
+    Ppm,
+    // Raw linear radiance as 32-bit floats (PFM), no gamma or clamping,
+    // for tone mapping and grading in other tools.
+    Pfm,
+}
+
+// How radiance is squeezed into [0, 1] for PPM output, before gamma. PFM is written untouched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    // Anything over 1.0 just clips to white.
+    Clamp,
+    // Extended Reinhard per channel, c(1 + c/white^2)/(1 + c): highlights roll off smoothly and
+    // radiance white reaches exactly 1.0. f64::INFINITY is plain Reinhard, c/(1 + c).
+    Reinhard { white: f64 },
+}
+
+impl ToneMap {
+    pub fn apply(&self, color: Vector3<f64>) -> Vector3<f64> {
+        match *self {
+            ToneMap::Clamp => color,
+            ToneMap::Reinhard { white } => color.map(|c| c * (1.0 + c / (white * white)) / (1.0 + c)),
+        }
+    }
+}
+
+// Reconstruction filter for the renderer. Each sample is splatted into every
+// pixel whose filter footprint it lands in, weighted by the filter, and each pixel ends up
+// the weighted average of what landed on it. radius is in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // Constant weight out to radius. A radius of 0.5 covers exactly one pixel,
+    // which is plain per-pixel averaging.
+    Box { radius: f64 },
+    // Weight falls off linearly to zero at radius.
+    Tent { radius: f64 },
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius } | Filter::Tent { radius } => radius,
+        }
+    }
+
+    // Weight of a sample at offset (dx, dy) pixels from a pixel's center.
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        match *self {
+            // Half open, so a sample on the shared edge of two pixels only lands in one.
+            Filter::Box { radius } => {
+                if dx >= -radius && dx < radius && dy >= -radius && dy < radius { 1.0 } else { 0.0 }
+            }
+            Filter::Tent { radius } => (1.0 - dx.abs() / radius).max(0.0) * (1.0 - dy.abs() / radius).max(0.0),
+        }
+    }
+}
+
+// Everything about a render that isn't the scene or camera.
+#[derive(Clone, Copy, Debug)]
+pub struct RenderSettings {
+    pub img_width: i32,
+    // Always from image_height, so it agrees with the camera's aspect ratio.
+    pub img_height: i32,
+    // Width over height of a single output pixel. 1.0 is square pixels,
+    // 2.0 stores a 2:1 anamorphic squeeze that gets stretched back out on display.
+    pub pixel_aspect_ratio: f64,
+    pub samples_per_pixel: i32,
+    pub max_depth: i32,
+    // Lens positions traced per antialiasing sample. Raising it smooths depth of field
+    // without paying for more antialiasing samples everywhere else.
+    pub lens_samples: i32,
+    // Bits per channel in the output, 8 or 16. 16 keeps smooth gradients from banding.
+    pub bit_depth: u32,
+    pub output_format: OutputFormat,
+    pub tone_map: ToneMap,
+    pub filter: Filter,
+    // Off puts every sample at the pixel center. With one sample per pixel and a 0.5 box
+    // filter that's hard, unaveraged edges, for pixel art or exact comparisons.
+    pub jitter: bool,
+    // (i, n): render only the ith of n horizontal bands, top band first, so n machines can
+    // split an image. (0, 1) is the whole image.
+    pub shard: (u32, u32),
+    // Same seed, same image, however rayon schedules the work.
+    pub seed: u64,
+}
+
+impl RenderSettings {
+    // Square pixels, one lens sample, jittered samples in a one pixel box, 8-bit clamped PPM,
+    // the whole image.
+    pub fn new(img_width: i32, img_height: i32, samples_per_pixel: i32, max_depth: i32, seed: u64) -> Self {
+        RenderSettings {
+            img_width,
+            img_height,
+            pixel_aspect_ratio: 1.0,
+            samples_per_pixel,
+            max_depth,
+            lens_samples: 1,
+            bit_depth: 8,
+            output_format: OutputFormat::Ppm,
+            tone_map: ToneMap::Clamp,
+            filter: Filter::Box { radius: 0.5 },
+            jitter: true,
+            shard: (0, 1),
+            seed,
+        }
+    }
+
+    // Largest channel value for the output bit depth, the PPM "maxval".
+    pub fn max_value(&self) -> i32 {
+        match self.bit_depth {
+            8 => 255,
+            16 => 65535,
+            other => panic!("bit depth must be 8 or 16, not {}", other),
+        }
+    }
+
+    // Width over height of the image as displayed, pixel aspect ratio and all.
+    pub fn aspect_ratio(&self) -> f64 {
+        self.img_width as f64 * self.pixel_aspect_ratio / self.img_height as f64
+    }
+
+    // A quarter of the size with at most 4 samples per pixel, for previews.
+    pub fn preview(&self) -> RenderSettings {
+        let img_width = (self.img_width / 4).max(2);
+        RenderSettings {
+            img_width,
+            img_height: image_height(img_width, self.aspect_ratio(), self.pixel_aspect_ratio).max(2),
+            samples_per_pixel: self.samples_per_pixel.clamp(1, 4),
+            output_format: OutputFormat::Ppm,
+            shard: (0, 1),
+            ..*self
+        }
+    }
+
+    // Rows of this settings' shard, counting down from the top row.
+    pub fn shard_rows(&self) -> std::ops::Range<i32> {
+        let (index, count) = self.shard;
+        let height = self.img_height as i64;
+        (index as i64 * height / count as i64) as i32..((index as i64 + 1) * height / count as i64) as i32
+    }
+
+    // Where in [0, 1) across a pixel the next sample goes, on each axis.
+    pub fn pixel_offset(&self) -> f64 {
+        if self.jitter { crate::random_double() } else { 0.5 }
+    }
+}
+
+// Renders every pixel of the settings' shard in parallel and returns their averaged colors,
+// top row first.
+pub fn trace_par(settings: &RenderSettings, camera: &Camera, scene: &Scene, integrator: &dyn Integrator) -> Vec<Vector3<f64>> {
+    trace_rows(settings, settings.shard_rows(), camera, scene, integrator)
+}
+
+// Averaged colors of rows (counting down from the top row), top row first. Seeds only
+// depend on the pass and row, so any split of the image into row ranges traces exactly
+// the samples the whole image would.
+pub(crate) fn trace_rows(settings: &RenderSettings, rows: std::ops::Range<i32>, camera: &Camera, scene: &Scene, integrator: &dyn Integrator) -> Vec<Vector3<f64>> {
+    let RenderSettings { img_width, img_height, samples_per_pixel, seed, filter, .. } = *settings;
+
+    // Samples from rows just outside the range still splat into its edge rows, so trace
+    // those too.
+    let margin = (filter.radius() - 0.5).max(0.0).ceil() as i32;
+    let traced_rows = (rows.start - margin).max(0)..(rows.end + margin).min(img_height);
+
+    let pixel_count = (img_width * img_height) as usize;
+    // Filter weighted sums of the samples that landed on each pixel, and the weights.
+    // Both are top row first, y counts up from the bottom.
+    let mut image = vec![Vector3::<f64>::new(0.0, 0.0, 0.0); pixel_count];
+    let mut weights = vec![0.0; pixel_count];
+
+    // Take one sample of every pixel per pass rather than finishing pixels one at a time,
+    // so a cancelled render is evenly sampled instead of partly black.
+    for samples_taken in 0..samples_per_pixel {
+        if CANCELLED.load(Ordering::SeqCst) {
+            eprintln!("Cancelled after {} of {} samples per pixel", samples_taken, samples_per_pixel);
+            break;
+        }
+
+        // Tracing is parallel, splatting is serial since a sample can land on neighbours.
+        // Each scanline of each pass is a tile with its own RNG stream.
+        let samples: Vec<(f64, f64, Vector3<f64>)> = traced_rows.clone().into_par_iter().flat_map_iter(|row| {
+            crate::seed_rng(seed, samples_taken as u64 * img_height as u64 + row as u64);
+            (0..img_width).map(|x| {
+                let s = x as f64 + settings.pixel_offset();
+                let t = (img_height - 1 - row) as f64 + settings.pixel_offset();
+                (s, t, sample_at(settings, camera, scene, integrator, s, t))
+            }).collect::<Vec<_>>()
+        }).collect();
+
+        for (s, t, color) in samples {
+            splat(settings, &mut image, &mut weights, s, t, color);
+        }
+    }
+
+    for (pixel, weight) in image.iter_mut().zip(weights.iter()) {
+        if *weight > 0.0 { *pixel /= *weight; }
+    }
+    image.drain((rows.start * img_width) as usize..(rows.end * img_width) as usize).collect()
+}
+
+// Add color, sampled at (s, t) in pixel units, to every pixel whose filter covers it.
+fn splat(settings: &RenderSettings, image: &mut [Vector3<f64>], weights: &mut [f64], s: f64, t: f64, color: Vector3<f64>) {
+    let RenderSettings { img_width, img_height, filter, .. } = *settings;
+    let radius = filter.radius();
+
+    // Pixel (x, y) has its center at (x + 0.5, y + 0.5).
+    let x_range = ((s - radius - 0.5).floor() as i32).max(0)..=((s + radius - 0.5).ceil() as i32).min(img_width - 1);
+    for y in ((t - radius - 0.5).floor() as i32).max(0)..=((t + radius - 0.5).ceil() as i32).min(img_height - 1) {
+        for x in x_range.clone() {
+            let weight = filter.weight(s - (x as f64 + 0.5), t - (y as f64 + 0.5));
+            if weight > 0.0 {
+                let i = ((img_height - 1 - y) * img_width + x) as usize;
+                image[i] += weight * color;
+                weights[i] += weight;
+            }
+        }
+    }
+}
+
+// Sample at (s, t) in pixel units, pixel (x, y) covering [x, x + 1) by [y, y + 1),
+// y counting up from the bottom.
+pub fn sample_at(settings: &RenderSettings, camera: &Camera, scene: &Scene, integrator: &dyn Integrator, s: f64, t: f64) -> Vector3<f64> {
+    let RenderSettings { img_width, img_height, max_depth, lens_samples, .. } = *settings;
+
+    let u: f64 = s/(img_width-1) as f64;
+    let v: f64 = t/(img_height-1) as f64;
+
+    // Every get_ray picks a new point on the lens, averaging them smooths defocus blur.
+    let lens_samples = lens_samples.max(1);
+    let lens_sum: Vector3<f64> = (0..lens_samples).map(|_| {
+        let r = camera.get_ray(u, v);
+        integrator.radiance(&r, scene, max_depth)
+    }).sum();
+    lens_sum / lens_samples as f64
+}
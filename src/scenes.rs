@@ -0,0 +1,272 @@
+use crate::background::Background;
+use crate::camera::CoordinateSystem;
+use crate::hittable_list::HittableList;
+use crate::material::*;
+use crate::sphere::Sphere;
+use crate::texture::CheckerTexture;
+use cgmath::*;
+use rand::Rng;
+use std::sync::Arc;
+
+// Radiance of the furnace's surroundings.
+pub const FURNACE_RADIANCE: f64 = 0.5;
+
+// The "white furnace" energy test: one diffuse sphere under a uniform background.
+// Light off a convex object never comes back to it, so the sphere should render flat
+// albedo * FURNACE_RADIANCE, and vanish completely at albedo 1. Any shading on it
+// means a material is losing or adding energy.
+pub fn furnace(albedo: f64) -> (HittableList, Background) {
+    furnace_with(Lambertian::new(Vector3::new(albedo, albedo, albedo)))
+}
+
+// The furnace with any material on the sphere, e.g. a Lambertian with another distribution.
+pub fn furnace_with<M: Material + 'static>(material: M) -> (HittableList, Background) {
+    let mut objects = HittableList::new();
+    objects.push(Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, material));
+
+    let radiance = Vector3::new(FURNACE_RADIANCE, FURNACE_RADIANCE, FURNACE_RADIANCE);
+    (objects, Background::Solid(radiance))
+}
+
+// The book's first scene (a diffuse, a glass and a metal sphere on a big ground sphere),
+// with a world-space checkerboard on the ground instead of flat yellow.
+// Positions are authored Y-up, right-handed, and placed through coordinates.
+pub fn default_with_checker_ground(coordinates: CoordinateSystem) -> HittableList {
+    let mut objects = HittableList::new();
+    let checker = CheckerTexture::new(Vector3::new(0.2, 0.3, 0.1), Vector3::new(0.9, 0.9, 0.9), 0.5);
+    objects.push(Sphere::new(coordinates.to_world(Vector3::new(0.0, -100.5, -1.0)), 100.0, Lambertian::textured(checker)));
+    objects.push(Sphere::new(coordinates.to_world(Vector3::new(0.0, 0.0, -1.0)), 0.5, Lambertian::new(Vector3::new(0.1, 0.2, 0.5))));
+    objects.push(Sphere::new(coordinates.to_world(Vector3::new(-1.0, 0.0, -1.0)), 0.5, Dielectric::new(1.5)));
+    // Negative radius flips the normals, making the glass sphere a hollow bubble.
+    objects.push(Sphere::new(coordinates.to_world(Vector3::new(-1.0, 0.0, -1.0)), -0.4, Dielectric::new(1.5)));
+    objects.push(Sphere::new(coordinates.to_world(Vector3::new(1.0, 0.0, -1.0)), 0.5, Metal::new(Vector3::new(0.8, 0.6, 0.2), 0.0)));
+    objects
+}
+
+// Give up on packing after this many rejected placements per requested sphere.
+const PACKING_ATTEMPTS_PER_SPHERE: usize = 100;
+
+// count non-overlapping spheres with random radii and materials, each fully inside the
+// box bounds = (min corner, max corner), in coordinates. Placement is by rejection, so a box
+// that's too full can come up short: the returned list then has fewer than count objects and
+// a warning is printed.
+pub fn packed_spheres<R: Rng + ?Sized>(count: usize, bounds: (Vector3<f64>, Vector3<f64>), coordinates: CoordinateSystem, rng: &mut R) -> HittableList {
+    let placed = pack_spheres(count, bounds, rng);
+    if placed.len() < count {
+        eprintln!("packed_spheres: only fit {} of {} spheres", placed.len(), count);
+    }
+
+    let mut objects = HittableList::new();
+    for (center, radius) in placed {
+        let center = coordinates.to_world(center);
+        let albedo = Vector3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>());
+        let material_to_use = rng.gen::<f64>();
+        if material_to_use < 0.8 {
+            objects.push(Sphere::new(center, radius, Lambertian::new(albedo)));
+        } else if material_to_use < 0.95 {
+            objects.push(Sphere::new(center, radius, Metal::new(albedo, rng.gen())));
+        } else {
+            objects.push(Sphere::new(center, radius, Dielectric::new(1.5)));
+        }
+    }
+    objects
+}
+
+// (center, radius) of up to count non-overlapping spheres inside bounds, for packed_spheres.
+fn pack_spheres<R: Rng + ?Sized>(count: usize, bounds: (Vector3<f64>, Vector3<f64>), rng: &mut R) -> Vec<(Vector3<f64>, f64)> {
+    let (min, max) = bounds;
+    let extent = max - min;
+    let smallest_extent = extent.x.min(extent.y).min(extent.z);
+    let max_radius = 0.1 * smallest_extent;
+    let min_radius = 0.25 * max_radius;
+
+    let mut placed: Vec<(Vector3<f64>, f64)> = Vec::new();
+    // NaN or infinite bounds would panic in gen_range.
+    if !max_radius.is_finite() || max_radius <= 0.0 {
+        eprintln!("packed_spheres: bounds have no volume, no spheres placed");
+        return placed;
+    }
+
+    let mut attempts = 0;
+    while placed.len() < count && attempts < count * PACKING_ATTEMPTS_PER_SPHERE {
+        attempts += 1;
+
+        let radius = rng.gen_range(min_radius, max_radius);
+        let center = Vector3::new(
+            rng.gen_range(min.x + radius, max.x - radius),
+            rng.gen_range(min.y + radius, max.y - radius),
+            rng.gen_range(min.z + radius, max.z - radius));
+
+        if placed.iter().any(|(other_center, other_radius)| (center - other_center).magnitude() < radius + other_radius) {
+            continue;
+        }
+        placed.push((center, radius));
+    }
+    placed
+}
+
+// Re-materials spheres by whatever assign picks for each, usually based on where it is,
+// e.g. metal below some plane and glass above it. Returning clones of the same Arc shares one
+// material between every sphere in a region.
+pub fn assign_materials<M, F>(spheres: Vec<Sphere<M>>, mut assign: F) -> HittableList
+where
+    M: Material,
+    F: FnMut(&Sphere<M>) -> Arc<dyn Material>,
+{
+    let mut objects = HittableList::new();
+    for sphere in spheres {
+        let material = assign(&sphere);
+        objects.push(sphere.with_material(material));
+    }
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use crate::distribution::{Hemisphere, InUnitSphere};
+    use crate::integrator::{Integrator, PathIntegrator, RayBias, Scene};
+    use rand::SeedableRng;
+
+    // Average radiance over a grid of camera rays, sphere in the middle, background at the edges.
+    fn render_furnace((objects, background): (HittableList, Background)) -> Vec<Vector3<f64>> {
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let camera = Camera::new(Vector3::new(0.0, 0.0, 4.0), Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), 40.0, 1.0, 0.0, 4.0);
+        let integrator = PathIntegrator::new();
+
+        crate::seed_rng(3, 0);
+        let mut image = Vec::new();
+        for y in 0..9 {
+            for x in 0..9 {
+                let ray = camera.get_ray(x as f64 / 8.0, y as f64 / 8.0);
+                let sum: Vector3<f64> = (0..64).map(|_| integrator.radiance(&ray, &scene, 50)).sum();
+                image.push(sum / 64.0);
+            }
+        }
+        image
+    }
+
+    fn unit_box() -> (Vector3<f64>, Vector3<f64>) {
+        (Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0))
+    }
+
+    fn packed(count: usize, bounds: (Vector3<f64>, Vector3<f64>)) -> HittableList {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(5);
+        packed_spheres(count, bounds, CoordinateSystem::native(), &mut rng)
+    }
+
+    #[test]
+    fn packed_spheres_dont_overlap_and_stay_in_bounds() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(5);
+        let spheres = pack_spheres(40, unit_box(), &mut rng);
+        assert_eq!(spheres.len(), 40);
+        for (i, (center, radius)) in spheres.iter().enumerate() {
+            for axis in 0..3 {
+                assert!(center[axis] - radius >= -1.0 && center[axis] + radius <= 1.0);
+            }
+            for (other_center, other_radius) in &spheres[i + 1..] {
+                assert!((center - other_center).magnitude() >= radius + other_radius);
+            }
+        }
+        assert_eq!(packed(40, unit_box()).len(), 40);
+    }
+
+    #[test]
+    fn overfull_box_comes_up_short() {
+        // Far more than random placement can fit before the gaps get too small.
+        let placed = packed(3000, unit_box()).len();
+        assert!(placed > 0 && placed < 3000, "{} placed", placed);
+    }
+
+    #[test]
+    fn degenerate_bounds_place_nothing() {
+        let nan = Vector3::new(f64::NAN, f64::NAN, f64::NAN);
+        assert!(packed(10, (nan, nan)).is_empty());
+        assert!(packed(10, (Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 1.0))).is_empty());
+    }
+
+    #[test]
+    fn white_furnace_sphere_disappears() {
+        for pixel in render_furnace(furnace(1.0)) {
+            assert!((pixel - Vector3::new(0.5, 0.5, 0.5)).magnitude() < 1e-3, "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn grey_furnace_sphere_is_albedo_times_the_surroundings() {
+        let image = render_furnace(furnace(0.5));
+        // Center pixel is the sphere, the corner the background.
+        assert!((image[4 * 9 + 4] - Vector3::new(0.25, 0.25, 0.25)).magnitude() < 1e-3);
+        assert!((image[0] - Vector3::new(0.5, 0.5, 0.5)).magnitude() < 1e-3);
+        for pixel in image {
+            assert!(((pixel.x - 0.25).abs() < 1e-3 || (pixel.x - 0.5).abs() < 1e-3), "{:?}", pixel);
+        }
+    }
+
+    #[test]
+    fn normalized_lambertians_pass_the_furnace_with_any_distribution() {
+        let grey = Vector3::new(0.5, 0.5, 0.5);
+        for image in [
+            render_furnace(furnace_with(Lambertian::with_distribution(grey, Hemisphere).normalized(true))),
+            render_furnace(furnace_with(Lambertian::with_distribution(grey, InUnitSphere).normalized(true))),
+            render_furnace(furnace_with(Lambertian::new(grey).normalized(true))),
+        ].iter() {
+            // Sphere pixels average to albedo times the surroundings, give or take the noise
+            // of weighting each sample by its pdf. InUnitSphere rarely samples near the
+            // horizon, so those samples carry big weights and it's the noisiest.
+            let sphere: Vec<f64> = image.iter().map(|pixel| pixel.x).filter(|x| (x - 0.5).abs() > 1e-9).collect();
+            let mean = sphere.iter().sum::<f64>() / sphere.len() as f64;
+            assert!(sphere.len() > 9);
+            assert!((mean - 0.25).abs() < 0.02, "mean {}", mean);
+        }
+    }
+
+    #[test]
+    fn assigned_materials_follow_the_region() {
+        use crate::hittable::Hittable;
+        use crate::ray::Ray;
+
+        // Red metal below y = 0, blue diffuse above, one material shared per region.
+        let (red, blue) = (Vector3::new(0.9, 0.1, 0.1), Vector3::new(0.1, 0.1, 0.9));
+        let below: Arc<dyn Material> = Arc::new(Metal::new(red, 0.0));
+        let above: Arc<dyn Material> = Arc::new(Lambertian::new(blue));
+        let centers: Vec<Vector3<f64>> = (0..6).map(|i| Vector3::new(i as f64 - 2.5, if i % 2 == 0 { -1.0 } else { 1.0 }, -3.0)).collect();
+        let spheres = centers.iter().map(|center| Sphere::new(*center, 0.4, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)))).collect();
+        let objects = assign_materials(spheres, |sphere| if sphere.center().y < 0.0 { below.clone() } else { above.clone() });
+        assert_eq!(objects.len(), 6);
+        assert_eq!((Arc::strong_count(&below), Arc::strong_count(&above)), (4, 4));
+
+        crate::seed_rng(4, 0);
+        for center in centers {
+            let ray = Ray::new(Vector3::new(center.x, center.y, 0.0), Vector3::new(0.0, 0.0, -1.0));
+            let hit = objects.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            let (_, atten) = hit.material.scatter(&ray, &hit).unwrap();
+            assert_eq!(atten, if center.y < 0.0 { red } else { blue });
+        }
+    }
+
+    #[test]
+    fn checker_ground_scene_has_a_two_tone_ground() {
+        use crate::hittable::Hittable;
+        use crate::ray::Ray;
+
+        let objects = default_with_checker_ground(CoordinateSystem::native());
+        // Ground, diffuse, glass with its bubble, and metal.
+        assert_eq!(objects.len(), 5);
+
+        // Straight down onto the ground in a line clear of the spheres.
+        crate::seed_rng(6, 0);
+        let mut albedos: Vec<Vector3<f64>> = Vec::new();
+        for i in 0..40 {
+            let ray = Ray::new(Vector3::new(0.1 * i as f64 - 2.0, 5.0, 1.0), Vector3::new(0.0, -1.0, 0.0));
+            let hit = objects.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            let (_, atten) = hit.material.scatter(&ray, &hit).unwrap();
+            if !albedos.contains(&atten) {
+                albedos.push(atten);
+            }
+        }
+        assert_eq!(albedos.len(), 2);
+        assert!(albedos.contains(&Vector3::new(0.2, 0.3, 0.1)) && albedos.contains(&Vector3::new(0.9, 0.9, 0.9)));
+    }
+}
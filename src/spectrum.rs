@@ -0,0 +1,103 @@
+use cgmath::*;
+
+// Visible range the color matching functions are integrated over, in nanometres.
+const VISIBLE_NM: (f64, f64) = (360.0, 830.0);
+
+// CIE 1931 color matching functions, as analytic fits (Wyman, Sloan & Shirley 2013)
+// rather than the tabulated data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cmf {
+    // Piecewise Gaussian lobes, within a few percent of the tables.
+    Cie1931MultiLobe,
+    // One lobe per function. Cheaper and rougher.
+    Cie1931SingleLobe,
+}
+
+impl Cmf {
+    // (x bar, y bar, z bar) at wavelength nm.
+    pub fn xyz(&self, nm: f64) -> Vector3<f64> {
+        match self {
+            Cmf::Cie1931MultiLobe => Vector3::new(
+                1.056 * lobe(nm, 599.8, 37.9, 31.0) + 0.362 * lobe(nm, 442.0, 16.0, 26.7) - 0.065 * lobe(nm, 501.1, 20.4, 26.2),
+                0.821 * lobe(nm, 568.8, 46.9, 40.5) + 0.286 * lobe(nm, 530.9, 16.3, 31.1),
+                1.217 * lobe(nm, 437.0, 11.8, 36.0) + 0.681 * lobe(nm, 459.0, 26.0, 13.8)),
+            Cmf::Cie1931SingleLobe => Vector3::new(
+                1.065 * gaussian((nm - 595.8) / 33.33) + 0.366 * gaussian((nm - 446.8) / 19.44),
+                1.014 * gaussian((nm.ln() - 556.3f64.ln()) / 0.075),
+                1.839 * gaussian((nm.ln() - 449.8f64.ln()) / 0.051)),
+        }
+    }
+}
+
+// Which illuminant comes out as neutral white.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WhitePoint {
+    // sRGB's own white, daylight.
+    D65,
+    // The equal-energy spectrum, flat across the visible range.
+    E,
+}
+
+// Linear sRGB of a spectrum given as (wavelength in nm, value) samples, in any order.
+// Each sample stands for the stretch of spectrum halfway to its neighbours, and a lone
+// sample for a 1 nm wide line. Scaled so a flat spectrum of 1.0 has Y = 1.0 (and, with
+// WhitePoint::E, comes out exactly (1, 1, 1)). Out of gamut colors, like most single
+// wavelengths, have negative channels, left for the caller to clip or map.
+pub fn spectrum_to_rgb(samples: &[(f64, f64)], cmf: Cmf, white: WhitePoint) -> Vector3<f64> {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut xyz = Vector3::new(0.0, 0.0, 0.0);
+    for (i, (nm, value)) in sorted.iter().enumerate() {
+        let below = if i > 0 { sorted[i - 1].0 } else { *nm };
+        let above = if i + 1 < sorted.len() { sorted[i + 1].0 } else { *nm };
+        let width = if sorted.len() == 1 { 1.0 } else { 0.5 * (above - below) };
+        xyz += *value * width * cmf.xyz(*nm);
+    }
+
+    // What a flat spectrum of 1.0 integrates to, 1 nm at a time.
+    let flat: Vector3<f64> = (VISIBLE_NM.0 as i32..=VISIBLE_NM.1 as i32).map(|nm| cmf.xyz(nm as f64)).sum();
+    let xyz = match white {
+        WhitePoint::D65 => xyz / flat.y,
+        // Equal energy is (1, 1, 1) in XYZ. Scale it to D65's white so sRGB shows it neutral.
+        WhitePoint::E => xyz.zip(flat, |c, f| c / f).zip(Vector3::new(0.95047, 1.0, 1.08883), |c, d| c * d),
+    };
+
+    Vector3::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z)
+}
+
+fn gaussian(x: f64) -> f64 {
+    (-0.5 * x * x).exp()
+}
+
+// Gaussian with a different width either side of its peak.
+fn lobe(nm: f64, peak: f64, width_below: f64, width_above: f64) -> f64 {
+    gaussian((nm - peak) / if nm < peak { width_below } else { width_above })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_spectrum_is_neutral_under_equal_energy_white() {
+        let flat: Vec<(f64, f64)> = (360..=830).map(|nm| (nm as f64, 0.5)).collect();
+        for cmf in [Cmf::Cie1931MultiLobe, Cmf::Cie1931SingleLobe].iter() {
+            let rgb = spectrum_to_rgb(&flat, *cmf, WhitePoint::E);
+            assert!((rgb.x - 0.5).abs() < 0.005 && (rgb.y - 0.5).abs() < 0.005 && (rgb.z - 0.5).abs() < 0.005,
+                "{:?} gave {:?}", cmf, rgb);
+        }
+    }
+
+    #[test]
+    fn red_line_is_saturated_red() {
+        for cmf in [Cmf::Cie1931MultiLobe, Cmf::Cie1931SingleLobe].iter() {
+            let rgb = spectrum_to_rgb(&[(650.0, 1.0)], *cmf, WhitePoint::D65);
+            assert!(rgb.x > 0.0, "{:?} gave {:?}", cmf, rgb);
+            assert!(rgb.y < 0.05 * rgb.x && rgb.z < 0.05 * rgb.x, "{:?} gave {:?}", cmf, rgb);
+        }
+    }
+}
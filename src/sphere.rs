@@ -2,26 +2,59 @@ use crate::material::Material;
 use crate::hittable::*;
 use cgmath::*;
 use crate::ray::Ray;
+use rand::{Rng, RngCore};
 
 pub struct Sphere<M: Material> {
     center: Vector3::<f64>,
     radius: f64,
     material: M,
+    // Added to u (mod 1), turning the texture around the poles and moving its seam.
+    u_offset: f64,
 }
 
 impl <M: Material> Sphere<M> {
     pub fn new(center: Vector3::<f64>, radius: f64, material: M) -> Sphere<M> {
         Self {
-            center: center,
-            radius: radius,
-            material: material,
+            center,
+            radius,
+            material,
+            u_offset: 0.0,
         }
     }
+
+    // Rotate where the texture wraps around the sphere, in turns: 0.5 puts the seam on
+    // the opposite side, to move it off something important in the texture.
+    pub fn with_u_offset(mut self, u_offset: f64) -> Self {
+        self.u_offset = u_offset;
+        self
+    }
+
+    pub fn center(&self) -> Vector3<f64> {
+        self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    // The same sphere with a different material.
+    pub fn with_material<N: Material>(self, material: N) -> Sphere<N> {
+        Sphere::new(self.center, self.radius, material).with_u_offset(self.u_offset)
+    }
 }
 
 impl<M: Material> Hittable for Sphere<M> {
     
-fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // A negative radius turns the sphere inside out (a bubble), so it has no inside.
+    fn contains(&self, point: Vector3<f64>) -> bool {
+        self.radius > 0.0 && (point - self.center).magnitude2() < self.radius * self.radius
+    }
+
+fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if ray.is_degenerate() {
+            return None
+        }
+
         let oc: Vector3<f64> = ray.origin - self.center;
 
         let a = ray.dir.magnitude2();
@@ -33,7 +66,10 @@ fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         } else {
             let sqrt_discriminant = discriminant.sqrt();
 
-            // find nearest root that lies in the acceptable range
+            // find nearest root that lies in the acceptable range.
+            // When the ray starts inside the sphere the near root is behind the origin,
+            // so we fall through to the far root: the exit point, which set_face_normal
+            // then reports with an inward normal and front_face == false.
             let mut root = (-half_b - sqrt_discriminant) / a;
             if root < t_min || root > t_max {
                 root = (-half_b + sqrt_discriminant) / a;
@@ -48,10 +84,136 @@ fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
             // to get a unit vector.
             let outward_normal = (point - self.center) / self.radius;
 
-            let mut hit: HitRecord = HitRecord { point: point, normal: outward_normal, t: root, front_face: false, material: &self.material };
+            let (u, v) = sphere_uv(outward_normal);
+            let u = (u + self.u_offset).rem_euclid(1.0);
+
+            let mut hit: HitRecord = HitRecord { point, normal: outward_normal, t: root, u, v, front_face: false, material: &self.material, object_id: BACKGROUND_ID };
             hit.set_face_normal(ray, outward_normal);
 
             Some(hit)
         }
     }
-}
\ No newline at end of file
+
+    // Directions are sampled uniformly over the cone the sphere subtends from origin.
+    fn pdf_value(&self, origin: Vector3<f64>, direction: Vector3<f64>) -> f64 {
+        if self.hit(&Ray::new(origin, direction), 0.001, f64::MAX).is_none() {
+            return 0.0
+        }
+
+        match cone_cos_theta_max(self.radius, (self.center - origin).magnitude2()) {
+            Some(cos_theta_max) => 1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_theta_max)),
+            // From inside the sphere every direction hits it.
+            None => 1.0 / (4.0 * std::f64::consts::PI),
+        }
+    }
+
+    fn random(&self, origin: Vector3<f64>, rng: &mut dyn RngCore) -> Vector3<f64> {
+        let direction = self.center - origin;
+        let cos_theta_max = match cone_cos_theta_max(self.radius, direction.magnitude2()) {
+            Some(cos_theta_max) => cos_theta_max,
+            None => return crate::sample_in_unit_sphere(rng).normalize(),
+        };
+
+        let r1: f64 = rng.gen();
+        let r2: f64 = rng.gen();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        // Build a frame around the direction to the center and place the sample in it.
+        let w = direction.normalize();
+        let helper = if w.x.abs() > 0.9 { Vector3::new(0.0, 1.0, 0.0) } else { Vector3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(helper).normalize();
+        let u = w.cross(v);
+        x * u + y * v + z * w
+    }
+}
+
+// Cosine of the half-angle of the cone a sphere subtends at distance_squared from its center.
+// None when the point is inside the sphere and there's no cone.
+fn cone_cos_theta_max(radius: f64, distance_squared: f64) -> Option<f64> {
+    let ratio = radius * radius / distance_squared;
+    if ratio >= 1.0 { None } else { Some((1.0 - ratio).sqrt()) }
+}
+
+// p is a point on the unit sphere centered at the origin.
+// u: angle around the Y axis from X=-1, v: angle from Y=-1 to Y=+1, both mapped to [0, 1].
+fn sphere_uv(p: Vector3<f64>) -> (f64, f64) {
+    let theta = (-p.y).acos();
+    let phi = (-p.z).atan2(p.x) + std::f64::consts::PI;
+
+    (phi / (2.0 * std::f64::consts::PI), theta / std::f64::consts::PI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn ray_from_the_center_exits_at_radius_facing_inward() {
+        let center = Vector3::new(1.0, 2.0, 3.0);
+        let sphere = Sphere::new(center, 2.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+        for dir in [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0), Vector3::new(1.0, 1.0, -1.0).normalize()].iter() {
+            let ray = Ray::new(center, *dir);
+            let hit = sphere.hit(&ray, 0.001, f64::INFINITY).expect("a ray from inside always exits");
+
+            assert!((hit.t - 2.5).abs() < 1e-9);
+            assert!(!hit.front_face);
+            // Inward: back towards the center and against the ray.
+            assert!((hit.normal + dir).magnitude() < 1e-9);
+            assert!(((hit.point - center).magnitude() - 2.5).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn cone_pdf_integrates_to_one() {
+        let sphere = Sphere::new(Vector3::new(0.3, -0.2, -4.0), 1.5, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let cos_theta_max = (1.0 - 1.5 * 1.5 / sphere.center.magnitude2()).sqrt();
+        let solid_angle = 2.0 * std::f64::consts::PI * (1.0 - cos_theta_max);
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+
+        // Sampled directions all land on the sphere, and 1/pdf averages to the cone's solid angle.
+        let samples = 10_000;
+        let mut inverse_sum = 0.0;
+        for _ in 0..samples {
+            let pdf = sphere.pdf_value(origin, sphere.random(origin, &mut rng));
+            assert!(pdf > 0.0);
+            inverse_sum += 1.0 / pdf;
+        }
+        assert!((inverse_sum / samples as f64 - solid_angle).abs() < 1e-6 * solid_angle);
+
+        // Over every direction the pdf integrates to 1, and it's 0 off the cone.
+        let mut pdf_sum = 0.0;
+        for _ in 0..samples * 10 {
+            let direction = crate::sample_in_unit_sphere(&mut rng).normalize();
+            let pdf = sphere.pdf_value(origin, direction);
+            if direction.dot(sphere.center.normalize()) < cos_theta_max - 1e-9 {
+                assert_eq!(pdf, 0.0);
+            }
+            pdf_sum += pdf;
+        }
+        let integral = 4.0 * std::f64::consts::PI * pdf_sum / (samples * 10) as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+        assert_eq!(sphere.pdf_value(origin, Vector3::new(0.0, 0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn u_offset_turns_the_texture_around_the_poles() {
+        let plain_sphere = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5)));
+        let turned_sphere = Sphere::new(Vector3::new(0.0, 0.0, 0.0), 1.0, Lambertian::new(Vector3::new(0.5, 0.5, 0.5))).with_u_offset(0.5);
+        for dir in [Vector3::new(1.0, 0.2, 0.0), Vector3::new(-0.3, -0.5, 1.0), Vector3::new(0.1, 0.9, -1.0), Vector3::new(-1.0, 0.0, -0.01)].iter() {
+            // From outside, straight at the center.
+            let ray = Ray::new(-4.0 * dir, *dir);
+            let plain = plain_sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            let turned = turned_sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+            assert_eq!(plain.point, turned.point);
+            assert_eq!(plain.v, turned.v);
+            assert!(((plain.u + 0.5).rem_euclid(1.0) - turned.u).abs() < 1e-12, "{} {}", plain.u, turned.u);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use crate::camera::Camera;
+use crate::integrator::{Integrator, Scene};
+use crate::render::{trace_rows, RenderSettings};
+use cgmath::*;
+use rayon::prelude::*;
+use std::sync::mpsc;
+
+// One finished band of full rows. y is its top row, counting down from the top of the
+// whole image. pixels are averaged linear colors, img_width * height of them, top row first.
+pub struct Tile {
+    pub y: i32,
+    pub height: i32,
+    pub pixels: Vec<Vector3<f64>>,
+}
+
+// Renders the settings' shard as bands of tile_rows rows (the last one shorter) in parallel
+// and hands each to on_tile as soon as it's done, on the calling thread, so a UI or network
+// stream can show progress. Bands are traced just like render::trace_par traces the whole
+// shard, every pass and filter splat included, so copying each tile into place at y rebuilds
+// exactly the image trace_par returns, whatever order they arrive in. A cancelled render
+// can leave bands that were further along with more passes than the rest.
+pub fn render_streaming<F: FnMut(Tile)>(settings: &RenderSettings, tile_rows: i32, camera: &Camera, scene: &Scene, integrator: &dyn Integrator, mut on_tile: F) {
+    let rows = settings.shard_rows();
+    let bands: Vec<i32> = rows.clone().step_by(tile_rows.max(1) as usize).collect();
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            bands.into_par_iter().for_each_with(sender, |sender, y| {
+                let height = tile_rows.max(1).min(rows.end - y);
+                let tile = Tile { y, height, pixels: trace_rows(settings, y..y + height, camera, scene, integrator) };
+                // The receiver only goes away if on_tile panicked, nothing left to do then.
+                let _ = sender.send(tile);
+            });
+        });
+
+        for tile in receiver {
+            on_tile(tile);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::background::Background;
+    use crate::hittable_list::HittableList;
+    use crate::integrator::{PathIntegrator, RayBias};
+    use crate::material::Lambertian;
+    use crate::render::{trace_par, Filter};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn tiles_arrive_once_each_and_rebuild_the_image() {
+        let mut objects = HittableList::new();
+        objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.5, 0.2, 0.2))));
+        let background = Background::Gradient { intensity: 1.0 };
+        let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+        let camera = Camera::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 90.0, 1.5, 0.0, 1.0);
+        // 7 bands of 4 rows, the last cut short. The tent filter splats across band edges.
+        let settings = RenderSettings { filter: Filter::Tent { radius: 1.5 }, ..RenderSettings::new(38, 25, 2, 4, 9) };
+
+        let stream = || {
+            let mut tiles = Vec::new();
+            let mut image = vec![None; (settings.img_width * settings.img_height) as usize];
+            render_streaming(&settings, 4, &camera, &scene, &PathIntegrator::new(), |tile| {
+                assert_eq!(tile.pixels.len(), (settings.img_width * tile.height) as usize);
+                for (i, pixel) in tile.pixels.iter().enumerate() {
+                    let slot = &mut image[tile.y as usize * settings.img_width as usize + i];
+                    assert!(slot.is_none(), "pixel {} of row {} came in two tiles", i, tile.y);
+                    *slot = Some(*pixel);
+                }
+                tiles.push(tile.y);
+            });
+            (tiles, image)
+        };
+
+        let (mut tiles, image) = stream();
+        assert_eq!(tiles.len(), 7);
+        tiles.sort_unstable();
+        tiles.dedup();
+        assert_eq!(tiles.len(), 7);
+        // The same pixels the binary's renderer makes, in any order the tiles finish.
+        let image: Vec<Vector3<f64>> = image.into_iter().map(Option::unwrap).collect();
+        assert_eq!(image, trace_par(&settings, &camera, &scene, &PathIntegrator::new()));
+        assert_eq!(stream().1.into_iter().map(Option::unwrap).collect::<Vec<_>>(), image);
+    }
+}
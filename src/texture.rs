@@ -0,0 +1,408 @@
+use cgmath::*;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// A color that can vary across a surface, looked up by surface (u, v) and/or world point p.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Vector3<f64>) -> Vector3<f64>;
+}
+
+pub struct SolidColor {
+    color: Vector3<f64>,
+}
+
+impl SolidColor {
+    pub fn new(color: Vector3<f64>) -> Self {
+        SolidColor {
+            color,
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: f64, _: f64, _: Vector3<f64>) -> Vector3<f64> {
+        self.color
+    }
+}
+
+// Checkerboard laid out in surface (u, v) space, so the grid follows the surface
+// instead of wobbling across it the way a world-space checker does on curved objects.
+pub struct UvCheckerTexture {
+    even: Vector3<f64>,
+    odd: Vector3<f64>,
+    u_tiles: f64,
+    v_tiles: f64,
+}
+
+impl UvCheckerTexture {
+    // u_tiles and v_tiles are how many squares fit across the full [0, 1] range of each.
+    pub fn new(even: Vector3<f64>, odd: Vector3<f64>, u_tiles: u32, v_tiles: u32) -> Self {
+        UvCheckerTexture {
+            even,
+            odd,
+            u_tiles: u_tiles.max(1) as f64,
+            v_tiles: v_tiles.max(1) as f64,
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, u: f64, v: f64, _: Vector3<f64>) -> Vector3<f64> {
+        let u_index = (u * self.u_tiles).floor() as i64;
+        let v_index = (v * self.v_tiles).floor() as i64;
+        if (u_index + v_index).rem_euclid(2) == 0 { self.even } else { self.odd }
+    }
+}
+
+// One tile of a texture atlas: incoming (u, v) in [0, 1] are squeezed into the
+// sub-rectangle [u_min, u_max] x [v_min, v_max] of the wrapped texture's own uv space,
+// so many textures packed into one can each be put on a surface by themselves.
+pub struct AtlasTexture<T: Texture> {
+    atlas: T,
+    u_min: f64,
+    v_min: f64,
+    u_max: f64,
+    v_max: f64,
+}
+
+impl<T: Texture> AtlasTexture<T> {
+    pub fn new(atlas: T, u_min: f64, v_min: f64, u_max: f64, v_max: f64) -> Self {
+        AtlasTexture {
+            atlas,
+            u_min,
+            v_min,
+            u_max,
+            v_max,
+        }
+    }
+}
+
+impl<T: Texture> Texture for AtlasTexture<T> {
+    fn value(&self, u: f64, v: f64, p: Vector3<f64>) -> Vector3<f64> {
+        // Clamp so uvs that stray just outside [0, 1] don't sample the neighbouring tile.
+        let u = self.u_min + u.clamp(0.0, 1.0) * (self.u_max - self.u_min);
+        let v = self.v_min + v.clamp(0.0, 1.0) * (self.v_max - self.v_min);
+        self.atlas.value(u, v, p)
+    }
+}
+
+// Treats the wrapped texture's values as sRGB encoded (how 8-bit color images are stored)
+// and decodes them to the linear values the renderer does its math in. Only wrap
+// color textures, data like roughness or normals is already linear.
+pub struct SrgbTexture<T: Texture> {
+    texture: T,
+}
+
+impl<T: Texture> SrgbTexture<T> {
+    pub fn new(texture: T) -> Self {
+        SrgbTexture {
+            texture,
+        }
+    }
+}
+
+impl<T: Texture> Texture for SrgbTexture<T> {
+    fn value(&self, u: f64, v: f64, p: Vector3<f64>) -> Vector3<f64> {
+        self.texture.value(u, v, p).map(srgb_to_linear)
+    }
+}
+
+// The sRGB transfer function, linear toe and all. 0.5 decodes to about 0.214.
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// 3D checkerboard of cubes size units across, laid out in world space. Unlike UvCheckerTexture
+// it doesn't pinch at a sphere's poles, which suits big ground spheres seen from near the top.
+pub struct CheckerTexture {
+    even: Vector3<f64>,
+    odd: Vector3<f64>,
+    size: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(even: Vector3<f64>, odd: Vector3<f64>, size: f64) -> Self {
+        CheckerTexture {
+            even,
+            odd,
+            size,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, _: f64, _: f64, p: Vector3<f64>) -> Vector3<f64> {
+        let cell = (p / self.size).map(|c| c.floor() as i64);
+        if (cell.x + cell.y + cell.z).rem_euclid(2) == 0 { self.even } else { self.odd }
+    }
+}
+
+const PERLIN_POINT_COUNT: usize = 256;
+
+// The book's gradient (Perlin) noise. Built from an rng so a seeded rng always gives
+// the same pattern.
+pub struct Perlin {
+    gradients: Vec<Vector3<f64>>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let gradients = (0..PERLIN_POINT_COUNT).map(|_| crate::sample_in_unit_sphere(rng).normalize()).collect();
+        let mut permutation = || {
+            let mut perm: Vec<usize> = (0..PERLIN_POINT_COUNT).collect();
+            perm.shuffle(rng);
+            perm
+        };
+        let perm_x = permutation();
+        let perm_y = permutation();
+        let perm_z = permutation();
+        Perlin {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    // Smooth noise in roughly [-1, 1], 0 at every integer lattice point.
+    pub fn noise(&self, p: Vector3<f64>) -> f64 {
+        let floor = p.map(f64::floor);
+        let f = p - floor;
+        // Hermite smoothing hides the grid the lattice would otherwise show.
+        let w = f.map(|c| c * c * (3.0 - 2.0 * c));
+        let (i, j, k) = (floor.x as i64, floor.y as i64, floor.z as i64);
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[((i + di) & 255) as usize]
+                        ^ self.perm_y[((j + dj) & 255) as usize]
+                        ^ self.perm_z[((k + dk) & 255) as usize];
+                    let (di, dj, dk) = (di as f64, dj as f64, dk as f64);
+                    let weight = (di * w.x + (1.0 - di) * (1.0 - w.x))
+                        * (dj * w.y + (1.0 - dj) * (1.0 - w.y))
+                        * (dk * w.z + (1.0 - dk) * (1.0 - w.z));
+                    sum += weight * self.gradients[index].dot(f - Vector3::new(di, dj, dk));
+                }
+            }
+        }
+        sum
+    }
+
+    // Sum of depth octaves of noise, each twice the frequency and half the amplitude.
+    pub fn turbulence(&self, p: Vector3<f64>, depth: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        sum.abs()
+    }
+}
+
+// Where a procedural texture looks up its pattern. World space puts the pattern in the
+// scene, so an object moved through it shows a different patch (the texture swims).
+// Object space measures from the object's origin (a sphere's center, say) instead, so the
+// pattern is locked to the object and moves with it. There are no instance transforms,
+// so the origin is given when the texture is made, along with the object.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseSpace {
+    World,
+    Object(Vector3<f64>),
+}
+
+impl NoiseSpace {
+    // World point p in this space.
+    pub fn local(&self, p: Vector3<f64>) -> Vector3<f64> {
+        match self {
+            NoiseSpace::World => p,
+            NoiseSpace::Object(origin) => p - origin,
+        }
+    }
+}
+
+// Wood grain: rings of light and dark around an axis, ring_spacing apart,
+// pushed in and out by turbulence (grain) so they wander like real growth rings.
+// The turbulence is sampled relative to the axis too, so moving the axis moves the whole
+// pattern with it.
+pub struct WoodTexture {
+    noise: Perlin,
+    light: Vector3<f64>,
+    dark: Vector3<f64>,
+    ring_spacing: f64,
+    grain: f64,
+    axis_origin: Vector3<f64>,
+    axis_direction: Vector3<f64>,
+    space: NoiseSpace,
+}
+
+impl WoodTexture {
+    // axis is (a point on the trunk's center line, its direction). grain around 1.0 gives
+    // gently wavy rings, 0.0 perfect circles.
+    pub fn new<R: Rng + ?Sized>(light: Vector3<f64>, dark: Vector3<f64>, ring_spacing: f64, grain: f64, axis: (Vector3<f64>, Vector3<f64>), rng: &mut R) -> Self {
+        let (axis_origin, axis_direction) = axis;
+        WoodTexture {
+            noise: Perlin::new(rng),
+            light,
+            dark,
+            ring_spacing,
+            grain,
+            axis_origin,
+            axis_direction: axis_direction.normalize(),
+            space: NoiseSpace::World,
+        }
+    }
+
+    // Evaluate in space, the axis then being given in that space too. Defaults to World.
+    pub fn with_noise_space(mut self, space: NoiseSpace) -> Self {
+        self.space = space;
+        self
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _: f64, _: f64, p: Vector3<f64>) -> Vector3<f64> {
+        let offset = self.space.local(p) - self.axis_origin;
+        let radius = (offset - offset.dot(self.axis_direction) * self.axis_direction).magnitude();
+        let rings = radius / self.ring_spacing + self.grain * self.noise.turbulence(offset, 7);
+        let t = 0.5 * (1.0 + (2.0 * std::f64::consts::PI * rings).sin());
+        (1.0 - t) * self.light + t * self.dark
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::sphere::Sphere;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const LIGHT: Vector3<f64> = Vector3 { x: 0.8, y: 0.6, z: 0.4 };
+    const DARK: Vector3<f64> = Vector3 { x: 0.3, y: 0.2, z: 0.1 };
+
+    fn wood(grain: f64, axis: (Vector3<f64>, Vector3<f64>), seed: u64) -> WoodTexture {
+        WoodTexture::new(LIGHT, DARK, 0.25, grain, axis, &mut ChaCha8Rng::seed_from_u64(seed))
+    }
+
+    fn y_axis_at(origin: Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+        (origin, Vector3::new(0.0, 1.0, 0.0))
+    }
+
+    #[test]
+    fn rings_repeat_every_ring_spacing() {
+        let texture = wood(0.0, y_axis_at(Vector3::new(0.0, 0.0, 0.0)), 1);
+        let at = |radius: f64, height: f64| texture.value(0.0, 0.0, Vector3::new(radius * 0.6, height, radius * 0.8));
+        for i in 0..20 {
+            let radius = 0.013 * i as f64;
+            assert!((at(radius, 0.0) - at(radius + 0.25, 0.0)).magnitude() < 1e-9);
+            assert!((at(radius, 0.0) - at(radius + 0.75, 3.0)).magnitude() < 1e-9);
+        }
+        // A quarter and three quarters of the way through a ring are fully dark and fully light.
+        assert!((at(0.0625, 0.0) - DARK).magnitude() < 1e-9);
+        assert!((at(0.1875, 0.0) - LIGHT).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn same_seed_same_grain() {
+        let axis = y_axis_at(Vector3::new(0.3, 0.0, -0.2));
+        let (a, b, other) = (wood(1.0, axis, 4), wood(1.0, axis, 4), wood(1.0, axis, 5));
+        let points: Vec<Vector3<f64>> = (0..50).map(|i| Vector3::new(0.1 * i as f64, 0.07 * i as f64, -0.05 * i as f64)).collect();
+        assert!(points.iter().all(|p| a.value(0.0, 0.0, *p) == b.value(0.0, 0.0, *p)));
+        assert!(points.iter().any(|p| a.value(0.0, 0.0, *p) != other.value(0.0, 0.0, *p)));
+    }
+
+    #[test]
+    fn grain_follows_the_axis() {
+        let shift = Vector3::new(5.3, -1.7, 2.9);
+        let here = wood(1.0, y_axis_at(Vector3::new(0.0, 0.0, 0.0)), 2);
+        let moved = wood(1.0, y_axis_at(shift), 2);
+        for i in 0..50 {
+            let p = Vector3::new(0.11 * i as f64, 0.05 * i as f64, 0.3 - 0.02 * i as f64);
+            assert!((here.value(0.0, 0.0, p) - moved.value(0.0, 0.0, p + shift)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn object_space_grain_moves_with_the_object() {
+        let shift = Vector3::new(5.3, -1.7, 2.9);
+        let (here, moved) = (Vector3::new(0.0, 0.0, 0.0), shift);
+        // The same axis through each sphere's center, given in object space.
+        let object = |center| wood(1.0, y_axis_at(Vector3::new(0.0, 0.0, 0.0)), 2).with_noise_space(NoiseSpace::Object(center));
+        let world = wood(1.0, y_axis_at(Vector3::new(0.0, 0.0, 0.0)), 2);
+
+        let mut swims = false;
+        for i in 0..50 {
+            let dir = Vector3::new(0.15 - 0.006 * i as f64, 0.1 - 0.005 * i as f64, -1.0);
+            let seen = |center: Vector3<f64>, texture: &WoodTexture| {
+                let sphere = Sphere::new(center, 1.0, Lambertian::new(LIGHT));
+                let hit = sphere.hit(&Ray::new(center + Vector3::new(0.0, 0.0, 3.0), dir), 0.001, f64::INFINITY).unwrap();
+                texture.value(hit.u, hit.v, hit.point)
+            };
+            assert!((seen(here, &object(here)) - seen(moved, &object(moved))).magnitude() < 1e-9);
+            swims |= (seen(here, &world) - seen(moved, &world)).magnitude() > 1e-3;
+        }
+        assert!(swims);
+    }
+
+    #[test]
+    fn uv_checker_flips_once_per_tile() {
+        let texture = UvCheckerTexture::new(LIGHT, DARK, 8, 4);
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        for (start, along_u) in [(0.01, true), (0.38, true), (0.02, false), (0.6, false)].iter() {
+            // One tile period is 1/8 across u and 1/4 across v, from just inside a tile.
+            let period = if *along_u { 1.0 / 8.0 } else { 1.0 / 4.0 };
+            let at = |step: i32| {
+                let t = start + period * step as f64 / 100.0;
+                if *along_u { texture.value(t, 0.3, origin) } else { texture.value(0.3, t, origin) }
+            };
+            let flips = (1..100).filter(|step| at(*step) != at(step - 1)).count();
+            assert_eq!(flips, 1, "{} {}", start, along_u);
+            assert_ne!(at(0), at(99));
+        }
+    }
+
+    // Shows the uv it was looked up at.
+    struct UvTexture;
+
+    impl Texture for UvTexture {
+        fn value(&self, u: f64, v: f64, _: Vector3<f64>) -> Vector3<f64> {
+            Vector3::new(u, v, 0.0)
+        }
+    }
+
+    #[test]
+    fn atlas_tiles_map_their_corners_onto_their_region() {
+        let tile = AtlasTexture::new(UvTexture, 0.5, 0.25, 0.75, 0.5);
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        assert_eq!(tile.value(0.0, 0.0, origin), Vector3::new(0.5, 0.25, 0.0));
+        assert_eq!(tile.value(1.0, 1.0, origin), Vector3::new(0.75, 0.5, 0.0));
+        assert_eq!(tile.value(0.5, 0.5, origin), Vector3::new(0.625, 0.375, 0.0));
+        // Strays outside [0, 1] stay on the tile's edge.
+        assert_eq!(tile.value(-0.1, 1.2, origin), Vector3::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn srgb_decodes_color_and_leaves_data_alone() {
+        let half = Vector3::new(0.5, 0.5, 0.5);
+        let decoded = SrgbTexture::new(SolidColor::new(half)).value(0.3, 0.7, Vector3::new(0.0, 0.0, 0.0));
+        assert!((decoded.x - 0.214).abs() < 1e-3, "{}", decoded.x);
+        assert_eq!(decoded, Vector3::new(decoded.x, decoded.x, decoded.x));
+        // The ends and the linear toe.
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+        assert_eq!(srgb_to_linear(0.02), 0.02 / 12.92);
+
+        // Data textures aren't wrapped, so they're used as is.
+        assert_eq!(SolidColor::new(half).value(0.3, 0.7, Vector3::new(0.0, 0.0, 0.0)), half);
+    }
+}
@@ -0,0 +1,43 @@
+// Cancellation goes through render::CANCELLED, which every render in the process reads, so
+// this lives alone in its own test binary where no other render can see the flag set.
+use cgmath::*;
+use raytracing::background::Background;
+use raytracing::camera::Camera;
+use raytracing::hittable_list::HittableList;
+use raytracing::integrator::{Integrator, PathIntegrator, RayBias, Scene};
+use raytracing::material::Lambertian;
+use raytracing::ray::Ray;
+use raytracing::render::{trace_par, Filter, RenderSettings, CANCELLED};
+use raytracing::sphere::Sphere;
+use std::sync::atomic::Ordering;
+
+// Path traces, and cancels the render from inside the first pass it's used in, like a
+// Ctrl-C arriving while that pass is running.
+struct CancelDuringPass;
+
+impl Integrator for CancelDuringPass {
+    fn radiance(&self, ray: &Ray, scene: &Scene, depth: i32) -> Vector3<f64> {
+        CANCELLED.store(true, Ordering::SeqCst);
+        PathIntegrator::new().radiance(ray, scene, depth)
+    }
+}
+
+#[test]
+fn cancelling_keeps_the_finished_passes_normalized() {
+    let mut objects = HittableList::new();
+    objects.push(Sphere::new(Vector3::new(0.0, -100.5, -1.0), 100.0, Lambertian::new(Vector3::new(0.6, 0.6, 0.6))));
+    objects.push(Sphere::new(Vector3::new(0.0, 0.0, -1.0), 0.5, Lambertian::new(Vector3::new(0.7, 0.3, 0.2))));
+    let background = Background::Gradient { intensity: 1.0 };
+    let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+    let camera = Camera::new(Vector3::new(0.0, 0.3, 1.0), Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, 1.0, 0.0), 60.0, 2.0, 0.0, 2.0);
+    // The tent filter gives every pixel a different total weight, so only dividing by the
+    // weights actually taken matches the single pass render.
+    let settings = RenderSettings { filter: Filter::Tent { radius: 1.5 }, ..RenderSettings::new(16, 8, 4, 8, 3) };
+
+    let cancelled = trace_par(&settings, &camera, &scene, &CancelDuringPass);
+    CANCELLED.store(false, Ordering::SeqCst);
+
+    let one_pass = trace_par(&RenderSettings { samples_per_pixel: 1, ..settings }, &camera, &scene, &PathIntegrator::new());
+    assert_eq!(cancelled, one_pass);
+    assert_ne!(one_pass, trace_par(&settings, &camera, &scene, &PathIntegrator::new()));
+}
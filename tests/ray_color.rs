@@ -0,0 +1,38 @@
+// The library's ray_color, called the way tools outside the crate would.
+use cgmath::*;
+use raytracing::background::Background;
+use raytracing::hittable_list::HittableList;
+use raytracing::integrator::{RayBias, Scene};
+use raytracing::material::Metal;
+use raytracing::ray::Ray;
+use raytracing::sphere::Sphere;
+
+const SKY: Vector3<f64> = Vector3 { x: 0.2, y: 0.4, z: 0.8 };
+
+fn mirror_ball() -> HittableList {
+    let mut objects = HittableList::new();
+    objects.push(Sphere::new(Vector3::new(0.0, 0.0, -2.0), 0.5, Metal::new(Vector3::new(0.5, 0.25, 1.0), 0.0)));
+    objects
+}
+
+#[test]
+fn direct_hit_on_a_mirror_sees_the_sky_through_its_albedo() {
+    let objects = mirror_ball();
+    let background = Background::Solid(SKY);
+    let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+    let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0));
+
+    let color = raytracing::ray_color(&ray, &scene, 50);
+    assert!((color - Vector3::new(0.1, 0.1, 0.8)).magnitude() < 1e-12, "{:?}", color);
+    // No bounces left to reach the sky after the hit.
+    assert_eq!(raytracing::ray_color(&ray, &scene, 1), Vector3::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn a_miss_is_the_background() {
+    let objects = mirror_ball();
+    let background = Background::Solid(SKY);
+    let scene = Scene { objects: &objects, background: &background, lighting: None, bias: RayBias::default() };
+    let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(raytracing::ray_color(&ray, &scene, 50), SKY);
+}